@@ -0,0 +1,79 @@
+//! 用 indicatif 为每个任务显示进度条
+//!
+//! 推荐的集成方式：下载事件决定何时创建和结束进度条，进度本身从管理器的快照读取，不额外发起 RPC。
+//!
+//! 运行：`cargo run --example progress --features progress -- <url>...`
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use burncloud_download_aria2::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use tokio::sync::broadcast::error::RecvError;
+
+#[tokio::main]
+async fn main() -> Aria2Result<()> {
+    let urls: Vec<String> = std::env::args().skip(1).collect();
+    if urls.is_empty() {
+        eprintln!("用法: progress <url>...");
+        return Ok(());
+    }
+
+    let manager = quick_start().await?;
+    let mut events = manager.subscribe();
+
+    let bars = MultiProgress::new();
+    let style = ProgressStyle::with_template("{msg:20!} [{bar:30}] {bytes}/{total_bytes} {binary_bytes_per_sec} {eta}")
+        .unwrap()
+        .progress_chars("=> ");
+    let mut tasks: HashMap<String, ProgressBar> = HashMap::new();
+    for url in urls {
+        let gid = manager.add_download(vec![url.clone()], None).await?;
+        let bar = bars.add(ProgressBar::new(0).with_style(style.clone()));
+        bar.set_message(url.rsplit('/').next().unwrap_or(&url).to_string());
+        tasks.insert(gid, bar);
+    }
+
+    let mut ticker = tokio::time::interval(Duration::from_millis(500));
+    while tasks.values().any(|bar| !bar.is_finished()) {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    // 错过的事件不影响进度，仍按快照刷新
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                match event {
+                    DownloadEvent::Completed { gid, .. } => {
+                        if let Some(bar) = tasks.get(&gid) {
+                            bar.finish();
+                        }
+                    }
+                    DownloadEvent::Failed { gid, error, .. } => {
+                        if let Some(bar) = tasks.get(&gid) {
+                            let reason = error.map_or_else(|| "未知错误".to_string(), |e| e.to_string());
+                            bar.abandon_with_message(format!("失败: {}", reason));
+                        }
+                    }
+                    DownloadEvent::Removed { gid } => {
+                        if let Some(bar) = tasks.get(&gid) {
+                            bar.abandon_with_message("已取消");
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ = ticker.tick() => {
+                for (gid, bar) in &tasks {
+                    if let Some(status) = manager.get_progress(gid) {
+                        bar.set_length(status.total_length.parse().unwrap_or(0));
+                        bar.set_position(status.completed_length.parse().unwrap_or(0));
+                    }
+                }
+            }
+        }
+    }
+
+    manager.shutdown().await
+}
@@ -0,0 +1,119 @@
+//! # 内嵌 REST API
+//!
+//! 在 [`Aria2Manager`] 之上提供一个简单的 HTTP/JSON 接口，
+//! 方便 BurnCloud 中非 Rust 的组件通过 localhost 控制下载。
+//!
+//! | 方法   | 路径                    | 说明         |
+//! |--------|-------------------------|--------------|
+//! | GET    | /downloads              | 列出所有任务 |
+//! | POST   | /downloads              | 添加下载     |
+//! | GET    | /downloads/:gid         | 查询进度     |
+//! | POST   | /downloads/:gid/pause   | 暂停         |
+//! | POST   | /downloads/:gid/resume  | 恢复         |
+//! | DELETE | /downloads/:gid         | 取消         |
+//!
+//! 添加和取消经由 [`Aria2Manager`]，与直接调用管理器的去重、任务记录和空闲重启规则相同；
+//! 添加种子、磁力链接或 Metalink 而 aria2 未启用相应功能时返回 422。
+
+use std::net::SocketAddr;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{Aria2Error, Aria2Manager, Aria2Result, Aria2RpcClient, DownloadOptions, DownloadStatus};
+
+/// 添加下载的请求体
+#[derive(Debug, Deserialize)]
+pub struct AddRequest {
+    pub uris: Vec<String>,
+    #[serde(default)]
+    pub options: Option<DownloadOptions>,
+}
+
+/// 构建 API 路由，可以嵌入到调用方已有的 axum 服务中
+pub fn router(manager: Aria2Manager) -> Router {
+    Router::new()
+        .route("/downloads", get(list_downloads).post(add_download))
+        .route("/downloads/:gid", get(get_download).delete(cancel_download))
+        .route("/downloads/:gid/pause", post(pause_download))
+        .route("/downloads/:gid/resume", post(resume_download))
+        .with_state(manager)
+}
+
+/// 在指定地址上启动 API 服务，直到出错才返回
+///
+/// 接口没有鉴权，应只绑定在 127.0.0.1 上。
+pub async fn serve(manager: Aria2Manager, addr: SocketAddr) -> Aria2Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .map_err(|e| Aria2Error::PortError(format!("绑定 {} 失败: {}", addr, e)))?;
+
+    axum::serve(listener, router(manager)).await
+        .map_err(|e| Aria2Error::DaemonError(format!("API 服务异常退出: {}", e)))
+}
+
+/// API 错误响应
+struct ApiError(Aria2Error);
+
+impl From<Aria2Error> for ApiError {
+    fn from(e: Aria2Error) -> Self {
+        Self(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            Aria2Error::ConfigError(_) => StatusCode::BAD_REQUEST,
+            Aria2Error::UnsupportedType(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Aria2Error::DaemonError(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Aria2Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Aria2Error::Cancelled => StatusCode::REQUEST_TIMEOUT,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        (status, Json(json!({ "error": self.0.to_string(), "code": self.0.code() }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+fn rpc_client(manager: &Aria2Manager) -> ApiResult<Aria2RpcClient> {
+    manager.create_rpc_client()
+        .ok_or_else(|| ApiError(Aria2Error::DaemonError("守护进程未运行".to_string())))
+}
+
+async fn list_downloads(State(manager): State<Aria2Manager>) -> ApiResult<Json<Vec<DownloadStatus>>> {
+    let client = rpc_client(&manager)?;
+    Ok(Json(client.tell_all().await?))
+}
+
+async fn add_download(State(manager): State<Aria2Manager>, Json(request): Json<AddRequest>) -> ApiResult<impl IntoResponse> {
+    let gid = manager.add_download(request.uris, request.options).await?;
+    Ok((StatusCode::CREATED, Json(json!({ "gid": gid }))))
+}
+
+async fn get_download(State(manager): State<Aria2Manager>, Path(gid): Path<String>) -> ApiResult<Json<DownloadStatus>> {
+    let client = rpc_client(&manager)?;
+    Ok(Json(client.tell_status(&gid).await?))
+}
+
+async fn pause_download(State(manager): State<Aria2Manager>, Path(gid): Path<String>) -> ApiResult<Json<serde_json::Value>> {
+    let client = rpc_client(&manager)?;
+    Ok(Json(json!({ "gid": client.pause(&gid).await? })))
+}
+
+async fn resume_download(State(manager): State<Aria2Manager>, Path(gid): Path<String>) -> ApiResult<Json<serde_json::Value>> {
+    let client = rpc_client(&manager)?;
+    let gid = client.unpause(&gid).await?;
+    manager.wake();
+    Ok(Json(json!({ "gid": gid })))
+}
+
+async fn cancel_download(State(manager): State<Aria2Manager>, Path(gid): Path<String>) -> ApiResult<Json<serde_json::Value>> {
+    manager.remove_download(&gid).await?;
+    Ok(Json(json!({ "gid": gid })))
+}
@@ -0,0 +1,290 @@
+//! # 同步接口
+//!
+//! 为不使用 async 的应用提供 [`Client`] 和 [`Manager`]，与 `reqwest::blocking` 类似，
+//! 内部持有一个 tokio 运行时并在其上阻塞执行对应的异步方法。
+//!
+//! 这些类型不能在异步上下文中使用（例如 `#[tokio::main]` 内），否则会 panic。
+
+use std::collections::HashMap;
+#[cfg(feature = "daemon")]
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::runtime::Runtime;
+
+use crate::{
+    Aria2Error, Aria2Result, Aria2RpcClient, DownloadOptions, DownloadStatus, FileInfo, GlobalProgress, GlobalStat,
+    VersionInfo,
+};
+#[cfg(feature = "daemon")]
+use crate::{Aria2Config, Aria2Manager, BootstrapPhase, CancellationToken, DownloadEvent, TaskRecord};
+
+/// 同步接口内部的运行时，关闭时一并丢弃该运行时的共享 HTTP 客户端
+struct BlockingRuntime(Runtime);
+
+impl std::ops::Deref for BlockingRuntime {
+    type Target = Runtime;
+
+    fn deref(&self) -> &Runtime {
+        &self.0
+    }
+}
+
+impl Drop for BlockingRuntime {
+    fn drop(&mut self) {
+        crate::release_http_client(self.0.handle().id());
+    }
+}
+
+fn runtime() -> Aria2Result<Arc<BlockingRuntime>> {
+    // 管理器的后台监视任务需要在阻塞调用之间继续运行，所以使用多线程运行时
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("burncloud-aria2")
+        .enable_all()
+        .build()
+        .map(|runtime| Arc::new(BlockingRuntime(runtime)))
+        .map_err(|e| Aria2Error::DaemonError(format!("创建运行时失败: {}", e)))
+}
+
+// ============================================================================
+// RPC 客户端
+// ============================================================================
+
+/// [`Aria2RpcClient`] 的同步版本
+pub struct Client {
+    inner: Aria2RpcClient,
+    runtime: Arc<BlockingRuntime>,
+}
+
+impl Client {
+    pub fn new(port: u16, secret: Option<String>) -> Aria2Result<Self> {
+        // 在运行时内创建，使用该运行时自己的 HTTP 客户端
+        let runtime = runtime()?;
+        let inner = {
+            let _guard = runtime.enter();
+            Aria2RpcClient::new(port, secret)
+        };
+        Ok(Self { inner, runtime })
+    }
+
+    /// 获取内部的异步客户端
+    pub fn as_async(&self) -> &Aria2RpcClient {
+        &self.inner
+    }
+
+    pub fn add_uri(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.add_uri(uris, options))
+    }
+
+    pub fn tell_status(&self, gid: &str) -> Aria2Result<DownloadStatus> {
+        self.runtime.block_on(self.inner.tell_status(gid))
+    }
+
+    pub fn tell_active(&self) -> Aria2Result<Vec<DownloadStatus>> {
+        self.runtime.block_on(self.inner.tell_active())
+    }
+
+    pub fn tell_waiting(&self, offset: u32, num: u32) -> Aria2Result<Vec<DownloadStatus>> {
+        self.runtime.block_on(self.inner.tell_waiting(offset, num))
+    }
+
+    pub fn tell_stopped(&self, offset: u32, num: u32) -> Aria2Result<Vec<DownloadStatus>> {
+        self.runtime.block_on(self.inner.tell_stopped(offset, num))
+    }
+
+    pub fn tell_all(&self) -> Aria2Result<Vec<DownloadStatus>> {
+        self.runtime.block_on(self.inner.tell_all())
+    }
+
+    pub fn get_files(&self, gid: &str) -> Aria2Result<Vec<FileInfo>> {
+        self.runtime.block_on(self.inner.get_files(gid))
+    }
+
+    pub fn get_global_stat(&self) -> Aria2Result<GlobalStat> {
+        self.runtime.block_on(self.inner.get_global_stat())
+    }
+
+    pub fn global_progress(&self) -> Aria2Result<GlobalProgress> {
+        self.runtime.block_on(self.inner.global_progress())
+    }
+
+    pub fn pause(&self, gid: &str) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.pause(gid))
+    }
+
+    pub fn unpause(&self, gid: &str) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.unpause(gid))
+    }
+
+    pub fn remove(&self, gid: &str) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.remove(gid))
+    }
+
+    pub fn force_pause(&self, gid: &str) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.force_pause(gid))
+    }
+
+    pub fn force_remove(&self, gid: &str) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.force_remove(gid))
+    }
+
+    pub fn pause_all(&self) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.pause_all())
+    }
+
+    pub fn unpause_all(&self) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.unpause_all())
+    }
+
+    pub fn get_option(&self, gid: &str) -> Aria2Result<HashMap<String, String>> {
+        self.runtime.block_on(self.inner.get_option(gid))
+    }
+
+    pub fn change_option(&self, gid: &str, options: HashMap<String, String>) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.change_option(gid, options))
+    }
+
+    pub fn get_global_option(&self) -> Aria2Result<HashMap<String, String>> {
+        self.runtime.block_on(self.inner.get_global_option())
+    }
+
+    pub fn change_global_option(&self, options: HashMap<String, String>) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.change_global_option(options))
+    }
+
+    pub fn purge_download_result(&self) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.purge_download_result())
+    }
+
+    pub fn get_version(&self) -> Aria2Result<VersionInfo> {
+        self.runtime.block_on(self.inner.get_version())
+    }
+
+    pub fn list_methods(&self) -> Aria2Result<Vec<String>> {
+        self.runtime.block_on(self.inner.list_methods())
+    }
+
+    pub fn list_notifications(&self) -> Aria2Result<Vec<String>> {
+        self.runtime.block_on(self.inner.list_notifications())
+    }
+
+    pub fn shutdown(&self) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+}
+
+// ============================================================================
+// 管理器
+// ============================================================================
+
+/// [`Aria2Manager`] 的同步版本
+///
+/// 后台监视任务运行在内部运行时的工作线程上。丢弃前应调用 [`Manager::shutdown`]，
+/// 否则运行时销毁后 aria2 进程不会被关闭。
+#[cfg(feature = "daemon")]
+#[derive(Clone)]
+pub struct Manager {
+    inner: Aria2Manager,
+    runtime: Arc<BlockingRuntime>,
+}
+
+#[cfg(feature = "daemon")]
+impl Manager {
+    pub fn new() -> Aria2Result<Self> {
+        Self::with_config(Aria2Config::default())
+    }
+
+    pub fn with_config(config: Aria2Config) -> Aria2Result<Self> {
+        let runtime = runtime()?;
+        let inner = {
+            let _guard = runtime.enter();
+            Aria2Manager::with_config(config)
+        };
+        Ok(Self { inner, runtime })
+    }
+
+    /// 获取内部的异步管理器
+    pub fn as_async(&self) -> &Aria2Manager {
+        &self.inner
+    }
+
+    pub fn config(&self) -> Aria2Config {
+        self.inner.config()
+    }
+
+    /// 下载并设置 aria2
+    pub fn download_and_setup(&self) -> Aria2Result<()> {
+        self.runtime.block_on(self.inner.download_and_setup())
+    }
+
+    pub fn download_and_setup_with_progress<F>(&self, progress: F) -> Aria2Result<()>
+    where
+        F: FnMut(BootstrapPhase, u64, u64),
+    {
+        let token = CancellationToken::new();
+        self.runtime.block_on(self.inner.download_and_setup_with_progress(&token, progress))
+    }
+
+    pub fn start_daemon(&self) -> Aria2Result<()> {
+        self.runtime.block_on(self.inner.start_daemon())
+    }
+
+    pub fn shutdown(&self) -> Aria2Result<()> {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    pub fn rpc_port(&self) -> Option<u16> {
+        self.inner.rpc_port()
+    }
+
+    /// 创建与管理器共用运行时的同步 RPC 客户端
+    pub fn rpc_client(&self) -> Option<Client> {
+        self.inner.create_rpc_client().map(|inner| Client { inner, runtime: self.runtime.clone() })
+    }
+
+    pub fn add_download(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        self.runtime.block_on(self.inner.add_download(uris, options))
+    }
+
+    pub fn remove_download(&self, gid: &str) -> Aria2Result<()> {
+        self.runtime.block_on(self.inner.remove_download(gid))
+    }
+
+    pub fn find_task(&self, uris: &[String], options: Option<&DownloadOptions>) -> Aria2Result<Option<String>> {
+        self.runtime.block_on(self.inner.find_task(uris, options))
+    }
+
+    pub fn get_progress(&self, gid: &str) -> Option<DownloadStatus> {
+        self.inner.get_progress(gid)
+    }
+
+    pub fn list_tasks(&self) -> Vec<DownloadStatus> {
+        self.inner.list_tasks()
+    }
+
+    pub fn task_records(&self) -> Aria2Result<Vec<TaskRecord>> {
+        self.runtime.block_on(self.inner.task_records())
+    }
+
+    pub fn import_url_list(&self, path: &Path) -> Aria2Result<Vec<(String, Aria2Result<String>)>> {
+        self.runtime.block_on(self.inner.import_url_list(path))
+    }
+
+    pub fn apply_profile(&self, name: &str) -> Aria2Result<()> {
+        self.runtime.block_on(self.inner.apply_profile(name))
+    }
+
+    pub fn retry_failed(&self) -> Aria2Result<Vec<String>> {
+        self.runtime.block_on(self.inner.retry_failed())
+    }
+
+    /// 订阅下载事件，可在任意线程上调用 `blocking_recv` 等待事件
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DownloadEvent> {
+        self.inner.subscribe()
+    }
+}
@@ -0,0 +1,137 @@
+//! # 剪贴板监视
+//!
+//! 与桌面下载工具类似，定期检查剪贴板，发现复制的 http(s) 或磁力链接时交给调用方确认，
+//! 确认后才加入下载队列。启动时剪贴板中已有的内容不会被提示。
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::{Aria2Manager, Aria2Result, CancellationToken, DownloadOptions};
+
+/// 剪贴板监视器
+pub struct ClipboardWatcher {
+    manager: Aria2Manager,
+    interval: Duration,
+    options: Option<DownloadOptions>,
+    confirm: Box<dyn FnMut(&str) -> bool + Send>,
+    // 上一次读到的剪贴板内容，None 表示还没有读取过
+    last: Option<String>,
+    seen: HashSet<String>,
+}
+
+impl ClipboardWatcher {
+    /// 创建剪贴板监视器，`confirm` 对每个新链接调用一次，返回 true 时添加下载
+    ///
+    /// `confirm` 在监视任务中同步调用，弹出对话框等耗时操作应在其他线程完成后再返回。
+    pub fn new(manager: Aria2Manager, confirm: impl FnMut(&str) -> bool + Send + 'static) -> Self {
+        Self {
+            manager,
+            interval: Duration::from_secs(1),
+            options: None,
+            confirm: Box::new(confirm),
+            last: None,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// 检查剪贴板的间隔，默认 1 秒
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 添加下载时使用的选项
+    pub fn with_options(mut self, options: DownloadOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// 检查剪贴板一次，返回新添加的任务 GID
+    ///
+    /// 剪贴板内容没有变化、不是文本或无法访问时不做任何事。
+    /// 单个链接添加失败时记录日志并继续处理其余链接，该链接再次被复制时会重新提示。
+    pub async fn poll_once(&mut self) -> Aria2Result<Vec<String>> {
+        let Some(text) = read_clipboard() else {
+            return Ok(Vec::new());
+        };
+        if self.last.as_ref() == Some(&text) {
+            return Ok(Vec::new());
+        }
+        let first = self.last.replace(text.clone()).is_none();
+        if first {
+            return Ok(Vec::new());
+        }
+
+        let mut added = Vec::new();
+        for link in find_links(&text) {
+            if !self.seen.insert(link.clone()) || !(self.confirm)(&link) {
+                continue;
+            }
+            match self.manager.add_download(vec![link.clone()], self.options.clone()).await {
+                Ok(gid) => {
+                    tracing::info!(gid = %gid, link = %link, "添加剪贴板中的链接");
+                    added.push(gid);
+                }
+                Err(e) => {
+                    tracing::warn!(link = %link, error = %e, "添加剪贴板链接失败");
+                    self.seen.remove(&link);
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// 持续监视剪贴板，直到令牌被取消
+    pub async fn run(mut self, token: CancellationToken) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = interval.tick() => {}
+            }
+
+            if let Err(e) = self.poll_once().await {
+                tracing::warn!(error = %e, "添加剪贴板链接失败");
+            }
+        }
+    }
+}
+
+fn read_clipboard() -> Option<String> {
+    arboard::Clipboard::new().ok()?.get_text().ok()
+}
+
+/// 提取文本中的 http(s) 和磁力链接，按出现顺序去重
+pub fn find_links(text: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let link = word.trim_matches(|c: char| matches!(c, '"' | '\'' | '<' | '>' | '(' | ')'));
+        let lower = link.to_ascii_lowercase();
+        let is_link = lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("magnet:?");
+        if is_link && !links.iter().any(|l| l == link) {
+            links.push(link.to_string());
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_links_in_text() {
+        let text = "下载 <https://example.com/a.zip> 和 (HTTP://Example.com/B.iso)\n\
+                    \"magnet:?xt=urn:btih:abc\" https://example.com/a.zip";
+        assert_eq!(
+            find_links(text),
+            vec!["https://example.com/a.zip", "HTTP://Example.com/B.iso", "magnet:?xt=urn:btih:abc"]
+        );
+    }
+
+    #[test]
+    fn find_links_ignores_other_schemes() {
+        assert!(find_links("ftp://example.com/a.zip magnet:abc https:/broken example.com").is_empty());
+        assert!(find_links("").is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! # RSS/Atom 订阅
+//!
+//! 定期拉取配置的 RSS 或 Atom 订阅，用正则匹配条目标题，
+//! 把匹配条目的附件（enclosure）或种子、磁力链接自动加入下载队列。
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use regex::Regex;
+use reqwest::Client;
+
+use crate::{http_client, Aria2Error, Aria2Manager, Aria2Result, CancellationToken, DownloadOptions};
+
+/// 订阅中的一个条目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    pub title: String,
+    pub link: String,
+}
+
+/// 一个订阅及其过滤规则
+#[derive(Debug, Clone, Default)]
+pub struct FeedRule {
+    /// 订阅地址
+    pub url: String,
+    /// 标题匹配的正则表达式，任意一个匹配即下载；为空时下载所有条目
+    pub filters: Vec<String>,
+    /// 添加下载时使用的选项
+    pub options: Option<DownloadOptions>,
+}
+
+/// 订阅监视器
+pub struct FeedWatcher {
+    manager: Aria2Manager,
+    client: Client,
+    interval: Duration,
+    feeds: Vec<(FeedRule, Vec<Regex>)>,
+    seen: HashSet<String>,
+}
+
+impl FeedWatcher {
+    /// 创建订阅监视器，`interval` 为两次拉取之间的间隔
+    pub fn new(manager: Aria2Manager, interval: Duration) -> Self {
+        Self {
+            manager,
+            client: http_client(),
+            interval,
+            feeds: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// 使用指定的 HTTP 客户端，而不是共享客户端
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// 添加订阅，过滤规则不是合法的正则表达式时返回错误
+    pub fn add_feed(&mut self, rule: FeedRule) -> Aria2Result<()> {
+        let filters = rule.filters.iter()
+            .map(|f| Regex::new(f).map_err(|e| Aria2Error::ConfigError(format!("无效的过滤规则 {}: {}", f, e))))
+            .collect::<Aria2Result<Vec<_>>>()?;
+        self.feeds.push((rule, filters));
+        Ok(())
+    }
+
+    /// 拉取所有订阅一次，返回新添加的任务 GID
+    ///
+    /// 单个订阅拉取或解析失败不影响其他订阅。
+    pub async fn poll_once(&mut self) -> Aria2Result<Vec<String>> {
+        let mut added = Vec::new();
+
+        for (rule, filters) in &self.feeds {
+            let entries = match fetch_feed(&self.client, &rule.url).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::warn!(url = %rule.url, error = %e, "拉取订阅失败");
+                    continue;
+                }
+            };
+
+            for entry in entries {
+                if self.seen.contains(&entry.link) {
+                    continue;
+                }
+                if !filters.is_empty() && !filters.iter().any(|f| f.is_match(&entry.title)) {
+                    continue;
+                }
+
+                let metadata = HashMap::from([
+                    ("feed".to_string(), rule.url.clone()),
+                    ("title".to_string(), entry.title.clone()),
+                ]);
+                // 添加失败的条目不记为已处理，下次拉取时再试
+                let gid = match self.manager
+                    .add_download_with_metadata(vec![entry.link.clone()], rule.options.clone(), metadata)
+                    .await
+                {
+                    Ok(gid) => gid,
+                    Err(e) => {
+                        tracing::warn!(link = %entry.link, error = %e, "添加订阅条目失败");
+                        continue;
+                    }
+                };
+                self.seen.insert(entry.link);
+                added.push(gid);
+            }
+        }
+
+        Ok(added)
+    }
+
+    /// 持续监视订阅，直到令牌被取消
+    pub async fn run(mut self, token: CancellationToken) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = interval.tick() => {}
+            }
+
+            if let Err(e) = self.poll_once().await {
+                tracing::warn!(error = %e, "订阅添加下载失败");
+            }
+        }
+    }
+}
+
+/// 下载并解析订阅
+pub async fn fetch_feed(client: &Client, url: &str) -> Aria2Result<Vec<FeedEntry>> {
+    let body = client.get(url)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| Aria2Error::DownloadError(format!("请求订阅失败: {}", e)))?
+        .text()
+        .await
+        .map_err(|e| Aria2Error::DownloadError(format!("读取订阅失败: {}", e)))?;
+    parse_feed(&body)
+}
+
+/// 解析 RSS 或 Atom 文档中的条目
+///
+/// 下载链接优先使用附件（RSS 的 `<enclosure url>`、Atom 的 `<link rel="enclosure">`），
+/// 没有附件时只接受指向种子或磁力链接的普通链接。
+pub fn parse_feed(xml: &str) -> Aria2Result<Vec<FeedEntry>> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = Vec::new();
+    let mut in_entry = false;
+    let mut in_title = false;
+    let mut in_link = false;
+    let mut title = String::new();
+    let mut enclosure: Option<String> = None;
+    let mut link: Option<String> = None;
+
+    loop {
+        let event = reader.read_event()
+            .map_err(|e| Aria2Error::DownloadError(format!("解析订阅失败: {}", e)))?;
+        match event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"item" | b"entry" => {
+                    in_entry = true;
+                    title.clear();
+                    enclosure = None;
+                    link = None;
+                }
+                b"title" if in_entry => in_title = true,
+                b"link" if in_entry => {
+                    in_link = true;
+                    read_link(&e, &mut enclosure, &mut link);
+                }
+                _ => {}
+            },
+            Event::Empty(e) if in_entry => match e.local_name().as_ref() {
+                b"enclosure" => enclosure = enclosure.or_else(|| attribute(&e, "url")),
+                b"link" => read_link(&e, &mut enclosure, &mut link),
+                _ => {}
+            },
+            Event::Text(t) => {
+                let text = t.unescape().map(|s| s.into_owned()).unwrap_or_default();
+                if in_title {
+                    title.push_str(&text);
+                } else if in_link && link.is_none() {
+                    link = Some(text);
+                }
+            }
+            Event::CData(c) => {
+                let text = String::from_utf8_lossy(&c.into_inner()).into_owned();
+                if in_title {
+                    title.push_str(&text);
+                } else if in_link && link.is_none() {
+                    link = Some(text);
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"item" | b"entry" => {
+                    in_entry = false;
+                    let target = enclosure.take().or_else(|| link.take().filter(|l| is_downloadable(l)));
+                    if let Some(target) = target {
+                        entries.push(FeedEntry { title: title.trim().to_string(), link: target });
+                    }
+                }
+                b"title" => in_title = false,
+                b"link" => in_link = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// 处理 Atom 的 `<link href rel>`，RSS 的 `<link>` 链接在文本中
+fn read_link(e: &BytesStart, enclosure: &mut Option<String>, link: &mut Option<String>) {
+    let Some(href) = attribute(e, "href") else {
+        return;
+    };
+    match attribute(e, "rel").as_deref() {
+        Some("enclosure") => *enclosure = enclosure.take().or(Some(href)),
+        None | Some("alternate") => *link = link.take().or(Some(href)),
+        _ => {}
+    }
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Option<String> {
+    e.try_get_attribute(name).ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+fn is_downloadable(link: &str) -> bool {
+    link.starts_with("magnet:") || link.split(['?', '#']).next().is_some_and(|p| p.ends_with(".torrent"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rss_items() {
+        let xml = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <title>频道标题</title>
+                <item>
+                    <title><![CDATA[Show S01E01]]></title>
+                    <link>https://example.com/post/1</link>
+                    <enclosure url="https://example.com/1.torrent" type="application/x-bittorrent"/>
+                </item>
+                <item>
+                    <title>Show S01E02 &amp; extras</title>
+                    <link>magnet:?xt=urn:btih:abc</link>
+                </item>
+                <item>
+                    <title>只有网页链接</title>
+                    <link>https://example.com/post/3</link>
+                </item>
+            </channel></rss>"#;
+
+        assert_eq!(
+            parse_feed(xml).unwrap(),
+            vec![
+                FeedEntry { title: "Show S01E01".into(), link: "https://example.com/1.torrent".into() },
+                FeedEntry { title: "Show S01E02 & extras".into(), link: "magnet:?xt=urn:btih:abc".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_atom_entries() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Feed</title>
+                <entry>
+                    <title>附件</title>
+                    <link rel="alternate" href="https://example.com/post/1"/>
+                    <link rel="enclosure" href="https://example.com/1.iso"/>
+                </entry>
+                <entry>
+                    <title>种子</title>
+                    <link href="https://example.com/2.torrent?key=1"/>
+                </entry>
+                <entry>
+                    <title>自引用</title>
+                    <link rel="self" href="https://example.com/3.torrent"/>
+                </entry>
+            </feed>"#;
+
+        assert_eq!(
+            parse_feed(xml).unwrap(),
+            vec![
+                FeedEntry { title: "附件".into(), link: "https://example.com/1.iso".into() },
+                FeedEntry { title: "种子".into(), link: "https://example.com/2.torrent?key=1".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_feed_rejects_malformed_xml() {
+        assert!(parse_feed("<rss><channel><item></channel></rss>").is_err());
+        assert!(parse_feed("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! # C 接口
+//!
+//! 以 `extern "C"` 函数导出下载管理器，供 BurnCloud 中用其他语言编写的组件嵌入同一套下载引擎。
+//! 基于 [`crate::blocking::Manager`] 实现，所有函数都是阻塞的。
+//!
+//! 返回 `int` 的函数成功时返回 0，失败时返回 -1，
+//! 可通过 [`burncloud_aria2_last_error`] 获取当前线程最近一次的错误信息。
+//!
+//! 构建 C 库：`cargo rustc --release --features ffi --crate-type staticlib`（或 `cdylib`）。
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::blocking::Manager;
+use crate::{Aria2Error, Aria2Result};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(e: &Aria2Error) {
+    let message = CString::new(e.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn report<T>(result: Aria2Result<T>) -> Option<T> {
+    result.map_err(|e| set_last_error(&e)).ok()
+}
+
+unsafe fn read_str<'a>(s: *const c_char, name: &str) -> Aria2Result<&'a str> {
+    if s.is_null() {
+        return Err(Aria2Error::ConfigError(format!("{} 不能为空", name)));
+    }
+    CStr::from_ptr(s).to_str()
+        .map_err(|_| Aria2Error::ConfigError(format!("{} 不是合法的 UTF-8", name)))
+}
+
+/// 任务状态
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BurnCloudAria2Status {
+    Active = 0,
+    Waiting = 1,
+    Paused = 2,
+    Complete = 3,
+    Error = 4,
+    Removed = 5,
+}
+
+/// 任务进度
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BurnCloudAria2Progress {
+    pub status: BurnCloudAria2Status,
+    pub total_length: u64,
+    pub completed_length: u64,
+    pub download_speed: u64,
+    pub upload_speed: u64,
+    pub upload_length: u64,
+    /// 已校验的字节数，没有在校验时为 0
+    pub verified_length: u64,
+    /// 当前连接数
+    pub connections: u32,
+    /// 完成百分比（0-100），总大小未知时为 -1
+    pub percent: f64,
+    /// aria2 的错误码，没有出错时为 0
+    pub error_code: c_int,
+}
+
+/// 创建管理器并下载、启动 aria2，失败时返回空指针
+///
+/// 返回的指针必须通过 [`burncloud_aria2_manager_free`] 释放。
+#[no_mangle]
+pub extern "C" fn burncloud_aria2_manager_new() -> *mut Manager {
+    let manager = report(Manager::new().and_then(|m| {
+        m.download_and_setup()?;
+        m.start_daemon()?;
+        Ok(m)
+    }));
+    manager.map_or(ptr::null_mut(), |m| Box::into_raw(Box::new(m)))
+}
+
+/// 关闭 aria2 并释放管理器，传入空指针时不做任何事
+///
+/// # Safety
+///
+/// `manager` 必须是 [`burncloud_aria2_manager_new`] 返回且尚未释放的指针。
+#[no_mangle]
+pub unsafe extern "C" fn burncloud_aria2_manager_free(manager: *mut Manager) {
+    if manager.is_null() {
+        return;
+    }
+    let manager = Box::from_raw(manager);
+    report(manager.shutdown());
+}
+
+/// 添加下载，返回任务 GID，失败时返回空指针
+///
+/// 返回的字符串必须通过 [`burncloud_aria2_string_free`] 释放。
+///
+/// # Safety
+///
+/// `manager` 必须是有效的管理器指针，`uri` 必须是以 NUL 结尾的字符串。
+#[no_mangle]
+pub unsafe extern "C" fn burncloud_aria2_add_download(manager: *const Manager, uri: *const c_char) -> *mut c_char {
+    let Some(manager) = manager.as_ref() else {
+        set_last_error(&Aria2Error::ConfigError("manager 不能为空".to_string()));
+        return ptr::null_mut();
+    };
+    let gid = report(read_str(uri, "uri").and_then(|uri| manager.add_download(vec![uri.to_string()], None)));
+    gid.and_then(|gid| CString::new(gid).ok()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// 查询任务进度并写入 `progress`
+///
+/// # Safety
+///
+/// `manager` 必须是有效的管理器指针，`gid` 必须是以 NUL 结尾的字符串，
+/// `progress` 必须指向可写的 [`BurnCloudAria2Progress`]。
+#[no_mangle]
+pub unsafe extern "C" fn burncloud_aria2_get_progress(
+    manager: *const Manager,
+    gid: *const c_char,
+    progress: *mut BurnCloudAria2Progress,
+) -> c_int {
+    let (Some(manager), false) = (manager.as_ref(), progress.is_null()) else {
+        set_last_error(&Aria2Error::ConfigError("manager 和 progress 不能为空".to_string()));
+        return -1;
+    };
+    let status = read_str(gid, "gid").and_then(|gid| match manager.get_progress(gid) {
+        Some(status) => Ok(status),
+        // 后台监视任务尚未获取到新添加的任务时直接查询 aria2
+        None => manager.rpc_client()
+            .ok_or_else(|| Aria2Error::DaemonError("守护进程未运行".to_string()))?
+            .tell_status(gid),
+    });
+    let Some(status) = report(status) else {
+        return -1;
+    };
+
+    *progress = BurnCloudAria2Progress {
+        status: match status.status.as_str() {
+            "active" => BurnCloudAria2Status::Active,
+            "paused" => BurnCloudAria2Status::Paused,
+            "complete" => BurnCloudAria2Status::Complete,
+            "error" => BurnCloudAria2Status::Error,
+            "removed" => BurnCloudAria2Status::Removed,
+            _ => BurnCloudAria2Status::Waiting,
+        },
+        total_length: status.total_length.parse().unwrap_or(0),
+        completed_length: status.completed_length.parse().unwrap_or(0),
+        download_speed: status.download_speed.parse().unwrap_or(0),
+        upload_speed: status.upload_speed.parse().unwrap_or(0),
+        upload_length: status.upload_length.parse().unwrap_or(0),
+        verified_length: status.verified_length.as_deref().and_then(|v| v.parse().ok()).unwrap_or(0),
+        connections: status.connections.parse().unwrap_or(0),
+        percent: status.percent().unwrap_or(-1.0),
+        error_code: status.error_code.as_deref().and_then(|c| c.parse().ok()).unwrap_or(0),
+    };
+    0
+}
+
+/// 取消任务
+///
+/// # Safety
+///
+/// `manager` 必须是有效的管理器指针，`gid` 必须是以 NUL 结尾的字符串。
+#[no_mangle]
+pub unsafe extern "C" fn burncloud_aria2_remove_download(manager: *const Manager, gid: *const c_char) -> c_int {
+    let Some(manager) = manager.as_ref() else {
+        set_last_error(&Aria2Error::ConfigError("manager 不能为空".to_string()));
+        return -1;
+    };
+    match report(read_str(gid, "gid").and_then(|gid| manager.remove_download(gid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// 释放本库返回的字符串，传入空指针时不做任何事
+///
+/// # Safety
+///
+/// `s` 必须是本库返回且尚未释放的字符串。
+#[no_mangle]
+pub unsafe extern "C" fn burncloud_aria2_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// 当前线程最近一次的错误信息，没有错误时返回空指针
+///
+/// 返回的字符串由本库持有，在当前线程下一次调用本库函数前有效，不要释放。
+#[no_mangle]
+pub extern "C" fn burncloud_aria2_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
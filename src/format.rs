@@ -0,0 +1,84 @@
+//! # 格式化
+//!
+//! 把字节数、速度和剩余时间格式化为便于阅读的文本，例如 `3.2 MB`、`3.2 MB/s`、`1h 05m`。
+//! 大小按 1024 进位，与 aria2 控制台输出一致。
+
+use std::time::Duration;
+
+const UNITS: [&str; 6] = ["B", "KB", "MB", "GB", "TB", "PB"];
+
+/// 格式化字节数，如 `512 B`、`3.2 MB`
+pub fn size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// 格式化速度（字节/秒），如 `3.2 MB/s`
+pub fn speed(bytes_per_sec: u64) -> String {
+    format!("{}/s", size(bytes_per_sec))
+}
+
+/// 格式化时长，只保留最大的两个单位，如 `45s`、`3m 05s`、`1h 05m`、`2d 03h`
+pub fn duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes, seconds) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d {:02}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// 按当前速度估算剩余时间，速度为 0 时返回 None
+pub fn eta(remaining_bytes: u64, bytes_per_sec: u64) -> Option<Duration> {
+    (bytes_per_sec > 0).then(|| Duration::from_secs(remaining_bytes.div_ceil(bytes_per_sec)))
+}
+
+/// 格式化剩余时间，无法估算时为 `--`
+pub fn eta_text(remaining_bytes: u64, bytes_per_sec: u64) -> String {
+    eta(remaining_bytes, bytes_per_sec).map_or_else(|| "--".to_string(), duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_switches_units_at_1024() {
+        assert_eq!(size(0), "0 B");
+        assert_eq!(size(1023), "1023 B");
+        assert_eq!(size(1024), "1.0 KB");
+        assert_eq!(size(3 * 1024 * 1024 + 200 * 1024), "3.2 MB");
+        assert_eq!(size(u64::MAX), "16384.0 PB");
+        assert_eq!(speed(2048), "2.0 KB/s");
+    }
+
+    #[test]
+    fn duration_keeps_two_largest_units() {
+        assert_eq!(duration(Duration::ZERO), "0s");
+        assert_eq!(duration(Duration::from_millis(59_999)), "59s");
+        assert_eq!(duration(Duration::from_secs(185)), "3m 05s");
+        assert_eq!(duration(Duration::from_secs(3600 + 5 * 60 + 59)), "1h 05m");
+        assert_eq!(duration(Duration::from_secs(2 * 86400 + 3 * 3600)), "2d 03h");
+    }
+
+    #[test]
+    fn eta_rounds_up_and_handles_zero_speed() {
+        assert_eq!(eta(1001, 100), Some(Duration::from_secs(11)));
+        assert_eq!(eta(0, 100), Some(Duration::ZERO));
+        assert_eq!(eta_text(100, 0), "--");
+    }
+}
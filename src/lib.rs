@@ -1,11 +1,16 @@
 //! # BurnCloud Aria2 下载库
 //!
 //! 这是一个简单的 Rust 库，用于下载、配置和管理 aria2 下载器。
-//! 遵循"极度简单"的设计原则，所有功能都在此文件中实现。
+//! 遵循"极度简单"的设计原则，所有核心功能都在此文件中实现，
+//! 由 feature 开启的可选功能位于独立模块中。
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+#[cfg(feature = "daemon")]
 use std::process::{Child, Command, Stdio};
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "daemon")]
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -13,701 +18,5460 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+pub use tokio_util::sync::CancellationToken;
+
+pub mod format;
+#[cfg(feature = "api")]
+pub mod api;
+#[cfg(feature = "webui")]
+pub mod webui;
+#[cfg(feature = "rss")]
+pub mod feed;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "pool")]
+pub mod pool;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+#[cfg(feature = "protocol-handler")]
+pub mod protocol;
+
 // 常量定义
 const DEFAULT_PORT: u16 = 6800;
+#[cfg(feature = "daemon")]
 const MAX_PORT_RANGE: u16 = 100;
+// 监控循环两次检查之间的挂钟间隔超过该值时，认为系统经历了睡眠
+#[cfg(feature = "daemon")]
+const SUSPEND_DETECT_GAP: Duration = Duration::from_secs(10);
+// 有因网络问题失败的任务时探测路由的最短和最长间隔，没有时按最长间隔更新基准
+#[cfg(feature = "daemon")]
+const NETWORK_PROBE_MIN: Duration = Duration::from_secs(5);
+#[cfg(feature = "daemon")]
+const NETWORK_PROBE_MAX: Duration = Duration::from_secs(60);
+#[cfg(feature = "daemon")]
 const ARIA2_MAIN_URL: &str = "https://github.com/aria2/aria2/releases/download/release-1.37.0/aria2-1.37.0-win-64bit-build1.zip";
+#[cfg(feature = "daemon")]
 const ARIA2_BACKUP_URL: &str = "https://gitee.com/burncloud/aria2/raw/master/aria2-1.37.0-win-64bit-build1.zip";
 
-/// 获取 BurnCloud 目录路径
-fn get_burncloud_dir() -> PathBuf {
-    std::env::var("USERPROFILE")
-        .map(|profile| PathBuf::from(profile).join("AppData").join("Local").join("BurnCloud"))
-        .unwrap_or_else(|_| PathBuf::from(r"C:\Users\Default\AppData\Local\BurnCloud"))
+// 通过 set_burncloud_dir 设置的 BurnCloud 目录
+static BURNCLOUD_DIR_OVERRIDE: std::sync::RwLock<Option<PathBuf>> = std::sync::RwLock::new(None);
+
+/// 设置 BurnCloud 目录，之后创建的默认配置、任务存储等都会使用该目录
+///
+/// 多个应用或用户需要隔离各自的 aria2 状态时使用，应在创建 [`Aria2Config`] 之前调用。
+pub fn set_burncloud_dir(dir: impl Into<PathBuf>) {
+    *BURNCLOUD_DIR_OVERRIDE.write().unwrap() = Some(dir.into());
 }
 
-// ============================================================================
-// 错误类型定义
-// ============================================================================
+/// 获取 BurnCloud 目录路径，存放 aria2 程序和 AriaNg 等数据
+///
+/// 依次使用 [`set_burncloud_dir`] 设置的目录、环境变量 `BURNCLOUD_DIR`、平台默认目录：
+/// - Windows：`%USERPROFILE%\AppData\Local\BurnCloud`
+/// - macOS：`~/Library/Application Support/BurnCloud`
+/// - Linux 等：`$XDG_DATA_HOME/burncloud`，默认 `~/.local/share/burncloud`
+pub fn get_burncloud_dir() -> PathBuf {
+    if let Some(dir) = burncloud_dir_override() {
+        return dir;
+    }
 
-#[derive(Debug)]
-pub enum Aria2Error {
-    DownloadError(String),
-    PortError(String),
-    RpcError(String),
-    DaemonError(String),
-    ProcessError(String),
-    ConfigError(String),
+    static MIGRATE: std::sync::Once = std::sync::Once::new();
+    MIGRATE.call_once(migrate_legacy_dir);
+    platform_data_dir()
 }
 
-impl std::fmt::Display for Aria2Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Aria2Error::DownloadError(msg) => write!(f, "下载错误: {}", msg),
-            Aria2Error::PortError(msg) => write!(f, "端口错误: {}", msg),
-            Aria2Error::RpcError(msg) => write!(f, "RPC错误: {}", msg),
-            Aria2Error::DaemonError(msg) => write!(f, "守护进程错误: {}", msg),
-            Aria2Error::ProcessError(msg) => write!(f, "进程错误: {}", msg),
-            Aria2Error::ConfigError(msg) => write!(f, "配置错误: {}", msg),
-        }
+/// 获取存放任务记录和下载历史等状态文件的目录
+///
+/// Linux 上为 `$XDG_STATE_HOME/burncloud`（默认 `~/.local/state/burncloud`），
+/// 其他平台以及设置了 BurnCloud 目录时与 [`get_burncloud_dir`] 相同。
+pub fn get_burncloud_state_dir() -> PathBuf {
+    match burncloud_dir_override() {
+        Some(dir) => dir,
+        None => platform_state_dir(),
     }
 }
 
-impl std::error::Error for Aria2Error {}
-
-pub type Aria2Result<T> = Result<T, Aria2Error>;
-
-// ============================================================================
-// 数据结构定义
-// ============================================================================
-
-#[derive(Debug, Clone)]
-pub struct Aria2Config {
-    pub port: u16,
-    pub secret: Option<String>,
-    pub download_dir: PathBuf,
-    pub max_connections: u8,
-    pub split_size: String,
-    pub aria2_path: PathBuf,
+fn burncloud_dir_override() -> Option<PathBuf> {
+    if let Some(dir) = BURNCLOUD_DIR_OVERRIDE.read().unwrap().clone() {
+        return Some(dir);
+    }
+    std::env::var_os("BURNCLOUD_DIR").filter(|d| !d.is_empty()).map(PathBuf::from)
 }
 
-impl Default for Aria2Config {
-    fn default() -> Self {
-        Self {
-            port: DEFAULT_PORT,
-            secret: None,
-            download_dir: std::env::current_dir().unwrap_or_default().join("downloads"),
-            max_connections: 16,
-            split_size: "1M".to_string(),
-            aria2_path: get_burncloud_dir().join("aria2c.exe"),
-        }
-    }
+#[cfg(not(unix))]
+fn platform_data_dir() -> PathBuf {
+    std::env::var("USERPROFILE")
+        .map(|profile| PathBuf::from(profile).join("AppData").join("Local").join("BurnCloud"))
+        .unwrap_or_else(|_| PathBuf::from(r"C:\Users\Default\AppData\Local\BurnCloud"))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DownloadOptions {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub dir: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub out: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub split: Option<u8>,
-    #[serde(rename = "max-connection-per-server", skip_serializing_if = "Option::is_none")]
-    pub max_connection_per_server: Option<u8>,
-    #[serde(rename = "continue", skip_serializing_if = "Option::is_none")]
-    pub continue_download: Option<bool>,
+#[cfg(target_os = "macos")]
+fn platform_data_dir() -> PathBuf {
+    home_dir().join("Library").join("Application Support").join("BurnCloud")
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct DownloadStatus {
-    pub gid: String,
-    pub status: String,
-    #[serde(rename = "totalLength")]
-    pub total_length: String,
-    #[serde(rename = "completedLength")]
-    pub completed_length: String,
-    #[serde(rename = "downloadSpeed")]
-    pub download_speed: String,
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+        .unwrap_or_else(|| home_dir().join(".local").join("share"))
+        .join("burncloud")
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct GlobalStat {
-    #[serde(rename = "downloadSpeed")]
-    pub download_speed: String,
-    #[serde(rename = "numActive")]
-    pub num_active: String,
-    #[serde(rename = "numWaiting")]
-    pub num_waiting: String,
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_state_dir() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+        .unwrap_or_else(|| home_dir().join(".local").join("state"))
+        .join("burncloud")
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct FileInfo {
-    pub path: String,
-    pub uris: Vec<UriInfo>,
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn platform_state_dir() -> PathBuf {
+    platform_data_dir()
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct UriInfo {
-    pub uri: String,
-    pub status: String,
+#[cfg(unix)]
+fn home_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(std::env::temp_dir)
 }
 
-pub struct Aria2Instance {
-    pub process: Child,
-    pub port: u16,
-    pub config: Aria2Config,
+/// XDG 基础目录：环境变量为绝对路径时使用它，否则使用 HOME 下的默认位置
+#[cfg(all(unix, not(target_os = "macos")))]
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
 }
 
-impl Aria2Instance {
-    pub fn is_running(&mut self) -> bool {
-        match self.process.try_wait() {
-            Ok(None) => true,
-            _ => false,
-        }
+/// 把旧版本使用的 `~/.burncloud` 迁移到平台目录，迁移失败时保留原目录
+#[cfg(unix)]
+fn migrate_legacy_dir() {
+    let legacy = home_dir().join(".burncloud");
+    let data_dir = platform_data_dir();
+    if !legacy.is_dir() || data_dir.exists() || legacy == data_dir {
+        return;
     }
 
-    pub fn kill(&mut self) -> Aria2Result<()> {
-        self.process.kill()
-            .map_err(|e| Aria2Error::ProcessError(e.to_string()))?;
-        self.process.wait()
-            .map_err(|e| Aria2Error::ProcessError(e.to_string()))?;
-        Ok(())
+    let moved = data_dir.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::rename(&legacy, &data_dir));
+    if let Err(e) = moved {
+        tracing::warn!(from = %legacy.display(), to = %data_dir.display(), error = %e, "迁移 BurnCloud 目录失败");
+        return;
+    }
+    tracing::info!(from = %legacy.display(), to = %data_dir.display(), "已迁移 BurnCloud 目录");
+
+    // 状态文件单独放在状态目录中
+    let state_dir = platform_state_dir();
+    if state_dir != data_dir {
+        for name in ["tasks.json", "history.jsonl"] {
+            let from = data_dir.join(name);
+            if from.exists() {
+                let _ = std::fs::create_dir_all(&state_dir);
+                let _ = std::fs::rename(&from, state_dir.join(name));
+            }
+        }
     }
 }
 
+#[cfg(not(unix))]
+fn migrate_legacy_dir() {}
+
 // ============================================================================
-// Aria2 下载功能
+// 共享 HTTP 客户端
 // ============================================================================
 
-/// 下载 aria2 二进制文件
-pub async fn download_aria2() -> Aria2Result<PathBuf> {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
-
-    let target_dir = get_burncloud_dir();
-    std::fs::create_dir_all(&target_dir)
-        .map_err(|e| Aria2Error::DownloadError(format!("创建目录失败: {}", e)))?;
-
-    let zip_path = target_dir.join("aria2.zip");
-    let exe_path = target_dir.join("aria2c.exe");
+/// HTTP 客户端配置，用于下载 aria2、探测下载地址、拉取 tracker 列表和 RPC 通信
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// 单个请求的总超时，None 表示不限制
+    #[serde(with = "option_duration_secs")]
+    pub timeout: Option<Duration>,
+    /// 建立连接的超时
+    #[serde(with = "option_duration_secs")]
+    pub connect_timeout: Option<Duration>,
+    /// 代理地址，如 "http://127.0.0.1:7890"；本机地址（aria2 RPC）始终直连
+    pub proxy: Option<String>,
+    /// 每个主机保留的最大空闲连接数
+    pub pool_max_idle_per_host: usize,
+    /// 空闲连接的保留时间，None 表示一直保留
+    #[serde(with = "option_duration_secs")]
+    pub pool_idle_timeout: Option<Duration>,
+    /// 关闭 Nagle 算法，降低小请求（如 RPC 调用）的延迟
+    pub tcp_nodelay: bool,
+    /// TCP keep-alive 探测间隔，None 表示不开启
+    #[serde(with = "option_duration_secs")]
+    pub tcp_keepalive: Option<Duration>,
+}
 
-    // 如果 exe 已存在，直接返回
-    if exe_path.exists() {
-        return Ok(exe_path);
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(30)),
+            connect_timeout: Some(Duration::from_secs(10)),
+            proxy: None,
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+        }
     }
+}
 
-    // 尝试主链接下载
-    match download_file(&client, ARIA2_MAIN_URL, &zip_path).await {
-        Ok(_) => println!("从主链接下载成功"),
-        Err(_) => {
-            println!("主链接下载失败，尝试备用链接...");
-            download_file(&client, ARIA2_BACKUP_URL, &zip_path).await
-                .map_err(|e| Aria2Error::DownloadError(format!("所有下载链接均失败: {}", e)))?;
-            println!("从备用链接下载成功");
+impl HttpConfig {
+    /// 适合频繁轮询本机 aria2 的 RPC 连接配置：保持少量长连接复用，避免反复建立连接
+    pub fn rpc() -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(5)),
+            pool_max_idle_per_host: 8,
+            pool_idle_timeout: None,
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            ..Self::default()
         }
     }
 
-    // 解压 ZIP 文件
-    extract_aria2(&zip_path, &target_dir)?;
-
-    // 删除 ZIP 文件
-    let _ = std::fs::remove_file(&zip_path);
-
-    if exe_path.exists() {
-        Ok(exe_path)
-    } else {
-        Err(Aria2Error::DownloadError("解压后未找到 aria2c.exe".to_string()))
+    /// 按配置创建 HTTP 客户端
+    pub fn build_client(&self) -> Aria2Result<Client> {
+        let mut builder = Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_nodelay(self.tcp_nodelay)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| Aria2Error::ConfigError(format!("无效的代理地址 {}: {}", proxy, e)))?
+                .no_proxy(reqwest::NoProxy::from_string("localhost,127.0.0.1,::1"));
+            builder = builder.proxy(proxy);
+        }
+        builder.build()
+            .map_err(|e| Aria2Error::ConfigError(format!("创建 HTTP 客户端失败: {}", e)))
     }
 }
 
-async fn download_file(client: &Client, url: &str, path: &Path) -> Aria2Result<()> {
-    let response = client.get(url).send().await
-        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
-
-    if !response.status().is_success() {
-        return Err(Aria2Error::DownloadError(format!("HTTP错误: {}", response.status())));
-    }
+// 共享 HTTP 客户端的配置，None 时使用默认配置
+static HTTP_CONFIG: Mutex<Option<HttpConfig>> = Mutex::new(None);
+// 按 tokio 运行时缓存的共享客户端：连接池中的连接由创建它的运行时驱动，
+// 运行时关闭后其他运行时再复用这些连接会失败，因此每个运行时使用各自的客户端。
+// 按最近使用的顺序排列，超过上限时淘汰最久未用的，避免不断创建又关闭的运行时让缓存无限增长
+static HTTP_CLIENTS: Mutex<Vec<(Option<tokio::runtime::Id>, Client)>> = Mutex::new(Vec::new());
+const MAX_HTTP_CLIENTS: usize = 16;
 
-    let bytes = response.bytes().await
-        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+fn current_runtime() -> Option<tokio::runtime::Id> {
+    tokio::runtime::Handle::try_current().ok().map(|handle| handle.id())
+}
 
-    std::fs::write(path, &bytes)
-        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+fn cache_http_client(clients: &mut Vec<(Option<tokio::runtime::Id>, Client)>, runtime: Option<tokio::runtime::Id>, client: Client) {
+    clients.retain(|(id, _)| *id != runtime);
+    if clients.len() >= MAX_HTTP_CLIENTS {
+        clients.remove(0);
+    }
+    clients.push((runtime, client));
+}
 
+/// 替换共享 HTTP 客户端的配置，之后创建的 RPC 客户端和发起的下载都会使用新配置
+///
+/// 按新配置创建客户端失败（如代理地址无效）时返回错误，原配置保持不变。
+pub fn set_http_config(config: &HttpConfig) -> Aria2Result<()> {
+    let client = config.build_client()?;
+    *HTTP_CONFIG.lock().unwrap() = Some(config.clone());
+    let mut clients = HTTP_CLIENTS.lock().unwrap();
+    clients.clear();
+    cache_http_client(&mut clients, current_runtime(), client);
     Ok(())
 }
 
-fn extract_aria2(zip_path: &Path, target_dir: &Path) -> Aria2Result<()> {
-    let file = std::fs::File::open(zip_path)
-        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
-
-    let mut archive = zip::ZipArchive::new(file)
-        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
-
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)
-            .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+/// 获取当前 tokio 运行时的共享 HTTP 客户端，克隆的客户端共用同一个连接池
+///
+/// 创建客户端失败时返回错误，不会退回到忽略代理等设置的默认客户端。
+pub fn try_http_client() -> Aria2Result<Client> {
+    let runtime = current_runtime();
+    let mut clients = HTTP_CLIENTS.lock().unwrap();
+    let client = match clients.iter().position(|(id, _)| *id == runtime) {
+        Some(index) => clients.remove(index).1,
+        None => HTTP_CONFIG.lock().unwrap().clone().unwrap_or_default().build_client()?,
+    };
+    cache_http_client(&mut clients, runtime, client.clone());
+    Ok(client)
+}
 
-        if file.name().ends_with("aria2c.exe") {
-            let mut out_file = std::fs::File::create(target_dir.join("aria2c.exe"))
-                .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
-            std::io::copy(&mut file, &mut out_file)
-                .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
-            return Ok(());
-        }
-    }
+/// 获取当前 tokio 运行时的共享 HTTP 客户端
+///
+/// 供只访问本机 aria2 等不能返回错误的场景使用；配置已由 [`set_http_config`] 校验，
+/// 仍然创建失败时（如 TLS 后端初始化失败）记录错误并使用默认客户端。
+/// 下载等访问外部网络的请求应使用 [`try_http_client`]。
+pub fn http_client() -> Client {
+    try_http_client().unwrap_or_else(|e| {
+        tracing::error!(error = %e, "按配置创建共享 HTTP 客户端失败，改用默认客户端");
+        Client::new()
+    })
+}
 
-    Err(Aria2Error::DownloadError("ZIP文件中未找到 aria2c.exe".to_string()))
+/// 运行时关闭时丢弃它的共享客户端
+#[cfg(feature = "blocking")]
+pub(crate) fn release_http_client(runtime: tokio::runtime::Id) {
+    HTTP_CLIENTS.lock().unwrap().retain(|(id, _)| *id != Some(runtime));
 }
 
 // ============================================================================
-// 端口管理
+// 错误类型定义
 // ============================================================================
 
-/// 检查端口是否可用
-pub fn check_port_available(port: u16) -> bool {
-    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+#[derive(Debug)]
+pub enum Aria2Error {
+    DownloadError(String),
+    PortError(String),
+    RpcError(String),
+    /// 带有方法、GID 和 URL 等上下文的 RPC 调用失败
+    RpcFailed(Box<RpcFailure>),
+    DaemonError(String),
+    ProcessError(String),
+    ConfigError(String),
+    ExtractError(String),
+    FileError(String),
+    /// aria2 编译时没有启用处理该类下载所需的功能（如 BitTorrent、Metalink）
+    UnsupportedType(String),
+    /// RPC 密钥错误或缺失，重试不会成功
+    Unauthorized(String),
+    Cancelled,
 }
 
-/// 查找可用端口
-pub fn find_available_port() -> Aria2Result<u16> {
-    for port in DEFAULT_PORT..=(DEFAULT_PORT + MAX_PORT_RANGE) {
-        if check_port_available(port) {
-            return Ok(port);
+/// 错误信息的显示语言
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    Chinese,
+    English,
+}
+
+impl Aria2Error {
+    /// 稳定的错误码，供下游应用按错误类别查找自己的翻译
+    pub fn code(&self) -> &'static str {
+        match self {
+            Aria2Error::DownloadError(_) => "download",
+            Aria2Error::PortError(_) => "port",
+            Aria2Error::RpcError(_) | Aria2Error::RpcFailed(_) => "rpc",
+            Aria2Error::DaemonError(_) => "daemon",
+            Aria2Error::ProcessError(_) => "process",
+            Aria2Error::ConfigError(_) => "config",
+            Aria2Error::ExtractError(_) => "extract",
+            Aria2Error::FileError(_) => "file",
+            Aria2Error::UnsupportedType(_) => "unsupported_type",
+            Aria2Error::Unauthorized(_) => "unauthorized",
+            Aria2Error::Cancelled => "cancelled",
         }
     }
-    Err(Aria2Error::PortError("未找到可用端口".to_string()))
-}
 
-/// 终止所有aria2c.exe进程
-pub fn kill_existing_aria2() {
-    let _ = Command::new("taskkill").args(["/F", "/IM", "aria2c.exe"]).output();
+    /// 用指定语言描述错误类别，`Display` 使用中文
+    ///
+    /// 只翻译类别前缀，详细信息（如底层 IO 错误或 aria2 返回的消息）保持原样。
+    pub fn localized(&self, lang: Language) -> String {
+        let english = lang == Language::English;
+        let (category, detail) = match self {
+            Aria2Error::DownloadError(msg) => (if english { "Download error" } else { "下载错误" }, msg.clone()),
+            Aria2Error::PortError(msg) => (if english { "Port error" } else { "端口错误" }, msg.clone()),
+            Aria2Error::RpcError(msg) => (if english { "RPC error" } else { "RPC错误" }, msg.clone()),
+            Aria2Error::RpcFailed(failure) => (if english { "RPC error" } else { "RPC错误" }, failure.localized(lang)),
+            Aria2Error::DaemonError(msg) => (if english { "Daemon error" } else { "守护进程错误" }, msg.clone()),
+            Aria2Error::ProcessError(msg) => (if english { "Process error" } else { "进程错误" }, msg.clone()),
+            Aria2Error::ConfigError(msg) => (if english { "Configuration error" } else { "配置错误" }, msg.clone()),
+            Aria2Error::ExtractError(msg) => (if english { "Extraction error" } else { "解压错误" }, msg.clone()),
+            Aria2Error::FileError(msg) => (if english { "File error" } else { "文件错误" }, msg.clone()),
+            Aria2Error::UnsupportedType(msg) => (if english { "Unsupported download type" } else { "不支持的下载类型" }, msg.clone()),
+            Aria2Error::Unauthorized(msg) => (if english { "Unauthorized" } else { "未授权" }, msg.clone()),
+            Aria2Error::Cancelled => return if english { "Operation cancelled" } else { "操作已取消" }.to_string(),
+        };
+        format!("{}: {}", category, detail)
+    }
 }
 
-/// 启动 aria2 RPC 服务
-pub async fn start_aria2_rpc(config: &Aria2Config) -> Aria2Result<Aria2Instance> {
-    // 先终止现有的aria2c.exe进程
-    kill_existing_aria2();
-
-    let port = find_available_port()?;
-
-    let mut cmd = Command::new(&config.aria2_path);
-    cmd.args([
-        "--enable-rpc",
-        "--rpc-listen-all",
-        &format!("--rpc-listen-port={}", port),
-        &format!("--dir={}", config.download_dir.display()),
-        &format!("--max-connection-per-server={}", config.max_connections),
-        &format!("--split={}", config.max_connections),
-        &format!("--min-split-size={}", config.split_size),
-        "--continue=true",
-        "--max-tries=0",
-        "--retry-wait=3",
-        "--daemon=true",
-    ]);
-
-    if let Some(secret) = &config.secret {
-        cmd.arg(&format!("--rpc-secret={}", secret));
+impl std::fmt::Display for Aria2Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localized(Language::Chinese))
     }
+}
 
-    let child = cmd
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| Aria2Error::ProcessError(e.to_string()))?;
-
-    let instance = Aria2Instance {
-        process: child,
-        port,
-        config: config.clone(),
-    };
-
-    // 等待 RPC 服务启动
-    wait_for_rpc_ready(port, &config.secret).await?;
+impl std::error::Error for Aria2Error {}
 
-    Ok(instance)
+/// RPC 调用失败的上下文，便于在日志和错误界面中定位是哪个任务出了问题
+#[derive(Debug, Clone)]
+pub struct RpcFailure {
+    /// 调用的方法，如 "aria2.tellStatus"
+    pub method: String,
+    /// 调用涉及的任务 GID
+    pub gid: Option<String>,
+    /// 调用涉及的下载地址（addUri 的第一个 URI）
+    pub url: Option<String>,
+    /// aria2 返回的错误码，连接失败或响应无法解析时为 None
+    pub code: Option<i64>,
+    pub message: String,
 }
 
-async fn wait_for_rpc_ready(port: u16, secret: &Option<String>) -> Aria2Result<()> {
-    let client = Client::new();
-    let url = format!("http://localhost:{}/jsonrpc", port);
+impl RpcFailure {
+    /// 根据方法和参数（不含 token）提取上下文
+    fn new(method: &str, params: &[Value], code: Option<i64>, message: impl Into<String>) -> Self {
+        // 以 GID 作为第一个参数的方法
+        const GID_METHODS: [&str; 15] = [
+            "aria2.remove", "aria2.forceRemove", "aria2.pause", "aria2.forcePause", "aria2.unpause",
+            "aria2.tellStatus", "aria2.getUris", "aria2.getFiles", "aria2.getPeers", "aria2.getServers",
+            "aria2.changePosition", "aria2.changeUri", "aria2.getOption", "aria2.changeOption",
+            "aria2.removeDownloadResult",
+        ];
 
-    for _ in 0..30 {
-        let mut params = vec![];
-        if let Some(s) = secret {
-            params.push(Value::String(format!("token:{}", s)));
-        }
+        let params: Vec<&Value> = params.iter()
+            .filter(|p| !p.as_str().is_some_and(|s| s.starts_with("token:")))
+            .collect();
+        let first = params.first();
 
-        let request = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": "test",
-            "method": "aria2.getVersion",
-            "params": params
-        });
+        let gid = GID_METHODS.contains(&method)
+            .then(|| first.and_then(|v| v.as_str()).map(str::to_string))
+            .flatten()
+            .or_else(|| params.iter().find_map(|p| p.get("gid")?.as_str().map(str::to_string)));
+        let url = (method == "aria2.addUri")
+            .then(|| first.and_then(|v| v.get(0)?.as_str()).map(str::to_string))
+            .flatten();
 
-        if let Ok(response) = client.post(&url).json(&request).send().await {
-            if response.status().is_success() {
-                return Ok(());
-            }
-        }
+        Self { method: method.to_string(), gid, url, code, message: message.into() }
+    }
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
+    /// 用指定语言描述失败的调用
+    pub fn localized(&self, lang: Language) -> String {
+        let mut text = self.method.clone();
+        if let Some(gid) = &self.gid {
+            text.push_str(&format!(" [gid {}]", gid));
+        }
+        if let Some(url) = &self.url {
+            text.push_str(&format!(" [{}]", url));
+        }
+        if let Some(code) = self.code {
+            let label = if lang == Language::English { "code" } else { "错误码" };
+            text.push_str(&format!(" {} {}", label, code));
+        }
+        format!("{}: {}", text, self.message)
     }
+}
 
-    Err(Aria2Error::RpcError("RPC 服务启动超时".to_string()))
+impl std::fmt::Display for RpcFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localized(Language::Chinese))
+    }
 }
 
+pub type Aria2Result<T> = Result<T, Aria2Error>;
+
 // ============================================================================
-// RPC 客户端
+// 数据结构定义
 // ============================================================================
 
-pub struct Aria2RpcClient {
-    client: Client,
-    base_url: String,
-    secret: Option<String>,
-    request_id: Arc<AtomicU64>,
-}
+/// aria2 守护进程配置
+///
+/// 可以从 TOML 文件反序列化（见 `Aria2Config::from_file`），缺少的字段使用默认值，
+/// 时间间隔以秒为单位。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Aria2Config {
+    /// 首选的 RPC 端口，被占用时按 `port_policy` 处理
+    pub port: u16,
+    /// 首选端口被占用时如何选择端口
+    pub port_policy: PortPolicy,
+    pub secret: Option<String>,
+    pub download_dir: PathBuf,
+    pub max_connections: u8,
+    pub split_size: String,
+    pub aria2_path: PathBuf,
+    /// 默认最大重试次数，0 表示无限重试
+    pub max_tries: u32,
+    /// 默认重试间隔秒数
+    pub retry_wait: u32,
+    /// 默认建立连接的超时秒数
+    pub connect_timeout: u32,
+    /// 默认接受 gzip 压缩传输
+    pub http_accept_gzip: bool,
+    /// 是否校验服务器证书
+    ///
+    /// aria2 的 TLS 上下文是进程级的，证书相关选项只能全局设置，无法按任务覆盖。
+    pub check_certificate: bool,
+    /// 额外信任的 CA 证书文件（PEM 格式），用于私有 CA 签发的服务器
+    pub ca_certificate: Option<PathBuf>,
+    /// .netrc 凭据文件路径，用于 HTTP/FTP 认证而不必把密码写进 URL
+    pub netrc_path: Option<PathBuf>,
+    /// 默认不使用 .netrc 中的凭据
+    pub no_netrc: bool,
+    /// 加载的 Netscape 格式 Cookie 文件，如浏览器导出的 cookies.txt
+    ///
+    /// aria2 只在启动时读取一次 --load-cookies，该选项不能按任务设置，也不能通过 changeGlobalOption 修改。
+    pub cookies_file: Option<PathBuf>,
+    /// 默认 User-Agent，部分站点会拦截 aria2 自带的 UA
+    pub user_agent: Option<String>,
+    /// 默认 Referer
+    pub referer: Option<String>,
+    /// 只接受加密的 BitTorrent 对等连接
+    pub bt_require_crypto: bool,
+    /// BitTorrent 最低加密级别："plain" 或 "arc4"
+    pub bt_min_crypto_level: Option<String>,
+    /// 每个 BitTorrent 下载的最大对等连接数，0 表示不限制
+    ///
+    /// aria2 没有按 IP 过滤对等节点的选项，也没有断开或封禁单个对等节点的 RPC 方法，
+    /// 因此本库不提供 IP 黑名单；需要时请在防火墙或路由器上拦截。
+    pub bt_max_peers: Option<u32>,
+    /// 为所有磁力链接和种子追加的 tracker 列表
+    pub bt_trackers: Vec<String>,
+    /// tracker 列表地址（如 trackerslist），每次启动时拉取并与 `bt_trackers` 合并
+    pub bt_tracker_list_url: Option<String>,
+    /// 网络恢复或切换后自动重试因网络问题失败的任务
+    pub retry_on_reconnect: bool,
+    /// 启动前终止本机已有的 aria2 进程，同时运行多个实例时应关闭
+    pub kill_existing: bool,
+    /// aria2 意外退出后最多自动重启的次数（包括启动失败的尝试），None 表示不限制
+    ///
+    /// 超过后守护进程停止重启，由管理器在另一个端口上启动备用守护进程，
+    /// 并从任务存储中重新添加未完成的任务。
+    pub max_restarts: Option<u32>,
+    /// 有任务在下载或排队时，后台监视任务刷新状态的间隔
+    #[serde(with = "duration_secs")]
+    pub poll_interval: Duration,
+    /// 没有活跃或等待任务时的刷新间隔，减少对 aria2 的请求和 CPU 唤醒
+    ///
+    /// 通过管理器添加或恢复任务会立即唤醒监视任务，该间隔只用于发现由其他工具添加的任务。
+    #[serde(with = "duration_secs")]
+    pub idle_poll_interval: Duration,
+    /// 没有活跃、等待或暂停的任务持续该时长后关闭 aria2 以节省内存，None 表示不自动关闭
+    ///
+    /// 关闭后通过管理器添加下载时会自动重新启动守护进程。
+    #[serde(with = "option_duration_secs")]
+    pub idle_shutdown: Option<Duration>,
+    /// 下载历史文件（JSON Lines），默认为 None，不记录历史
+    ///
+    /// 库不会擅自在磁盘上留下文件，需要时可设为 BurnCloud 状态目录下的 history.jsonl 等位置。
+    pub history_file: Option<PathBuf>,
+    /// 累计传输量的保存位置，默认为 None，只统计本次运行
+    pub stats_file: Option<PathBuf>,
+    /// RPC 调用耗时超过该值时记录警告日志，None 表示不警告
+    #[serde(with = "option_duration_secs")]
+    pub slow_rpc_threshold: Option<Duration>,
+    /// 守护进程 RPC 客户端使用的连接池配置，与下载等使用的共享客户端分开
+    pub rpc_http: HttpConfig,
+    /// 保留最近多少次原始 RPC 交互（见 [`RpcDump`]），0 表示不保留
+    pub rpc_dump_capacity: usize,
+    /// 守护进程 RPC 客户端的限流设置，None 表示不限流
+    pub rpc_rate_limit: Option<RpcRateLimit>,
+    /// 事件日志文件（JSON Lines），记录每个下载事件，用于审计和排查问题
+    ///
+    /// 默认不记录，可设为 BurnCloud 目录下的 events.jsonl 等位置。
+    pub event_log: Option<PathBuf>,
+    /// 下载完成时执行的命令（aria2 的 --on-download-complete）
+    pub on_download_complete: Option<PathBuf>,
+    /// 下载出错时执行的命令（aria2 的 --on-download-error）
+    pub on_download_error: Option<PathBuf>,
+    /// 在所有网卡上监听 RPC，默认只监听本机回环地址
+    ///
+    /// 开启后局域网内的其他设备也能控制 aria2，应同时设置 `secret`。
+    pub rpc_listen_all: bool,
+    /// 在 RPC 响应中添加 `Access-Control-Allow-Origin: *`，供直接连接 aria2 的网页界面使用
+    pub rpc_allow_origin_all: bool,
+    /// RPC 请求的最大长度，如 "2M"，None 时使用 aria2 的默认值
+    pub rpc_max_request_size: Option<String>,
+    /// 可通过 [`Aria2Manager::apply_profile`] 切换的限速方案，默认为 [`SpeedProfile::presets`]
+    pub speed_profiles: Vec<SpeedProfile>,
+    /// 暂停全部下载的时间段，结束后自动恢复被暂停的任务
+    pub quiet_hours: Vec<QuietHours>,
+    /// 额外传给 aria2 的命令行参数，如 "--disk-cache=64M"
+    pub extra_args: Vec<String>,
+}
+
+impl Aria2Config {
+    /// 便携模式：aria2 程序、任务记录、历史和下载文件都放在同一个根目录下
+    ///
+    /// `root` 为 None 时使用可执行文件旁边的 `BurnCloud` 目录，适合从 U 盘运行或按项目隔离。
+    /// 会调用 [`set_burncloud_dir`]，之后创建的任务存储等也使用该目录。
+    pub fn portable(root: Option<PathBuf>) -> Aria2Result<Self> {
+        let root = match root {
+            Some(root) => root,
+            None => std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.join("BurnCloud")))
+                .ok_or_else(|| Aria2Error::ConfigError("无法确定可执行文件所在目录".to_string()))?,
+        };
+        set_burncloud_dir(&root);
+
+        Ok(Self {
+            download_dir: root.join("downloads"),
+            ..Self::default()
+        })
+    }
+
+    /// 以默认配置为基础构建配置，`build()` 时检查端口、下载目录和选项
+    pub fn builder() -> Aria2ConfigBuilder {
+        Aria2ConfigBuilder { config: Aria2Config::default() }
+    }
+
+    /// 从 TOML 文件加载配置，再应用环境变量覆盖（见 [`Aria2Config::apply_env`]）
+    #[cfg(feature = "config-file")]
+    pub fn from_file(path: impl AsRef<Path>) -> Aria2Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Aria2Error::ConfigError(format!("读取 {} 失败: {}", path.display(), e)))?;
+        let mut config: Aria2Config = toml::from_str(&text)
+            .map_err(|e| Aria2Error::ConfigError(format!("解析 {} 失败: {}", path.display(), e)))?;
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 用 `BURNCLOUD_ARIA2_*` 环境变量覆盖配置，便于在不重新编译的情况下调整守护进程
+    ///
+    /// 支持的变量：`PORT`、`SECRET`、`DOWNLOAD_DIR`、`ARIA2_PATH`、`MAX_CONNECTIONS`、
+    /// `SPLIT_SIZE`、`MAX_TRIES`、`RETRY_WAIT`、`CONNECT_TIMEOUT`、`USER_AGENT`、
+    /// `CHECK_CERTIFICATE`、`COOKIES_FILE`、`KILL_EXISTING`，例如 `BURNCLOUD_ARIA2_PORT=6900`。
+    pub fn apply_env(&mut self) -> Aria2Result<()> {
+        fn var(name: &str) -> Option<String> {
+            std::env::var(format!("BURNCLOUD_ARIA2_{}", name)).ok()
+        }
+
+        fn parse<T: std::str::FromStr>(name: &str) -> Aria2Result<Option<T>> {
+            var(name)
+                .map(|value| value.parse().map_err(|_| {
+                    Aria2Error::ConfigError(format!("环境变量 BURNCLOUD_ARIA2_{} 的值无效: {}", name, value))
+                }))
+                .transpose()
+        }
+
+        if let Some(port) = parse("PORT")? {
+            self.port = port;
+        }
+        if let Some(secret) = var("SECRET") {
+            self.secret = Some(secret);
+        }
+        if let Some(dir) = var("DOWNLOAD_DIR") {
+            self.download_dir = PathBuf::from(dir);
+        }
+        if let Some(path) = var("ARIA2_PATH") {
+            self.aria2_path = PathBuf::from(path);
+        }
+        if let Some(max_connections) = parse("MAX_CONNECTIONS")? {
+            self.max_connections = max_connections;
+        }
+        if let Some(split_size) = var("SPLIT_SIZE") {
+            self.split_size = split_size;
+        }
+        if let Some(max_tries) = parse("MAX_TRIES")? {
+            self.max_tries = max_tries;
+        }
+        if let Some(retry_wait) = parse("RETRY_WAIT")? {
+            self.retry_wait = retry_wait;
+        }
+        if let Some(connect_timeout) = parse("CONNECT_TIMEOUT")? {
+            self.connect_timeout = connect_timeout;
+        }
+        if let Some(user_agent) = var("USER_AGENT") {
+            self.user_agent = Some(user_agent);
+        }
+        if let Some(check_certificate) = parse("CHECK_CERTIFICATE")? {
+            self.check_certificate = check_certificate;
+        }
+        if let Some(path) = var("COOKIES_FILE") {
+            self.cookies_file = Some(PathBuf::from(path));
+        }
+        if let Some(kill_existing) = parse("KILL_EXISTING")? {
+            self.kill_existing = kill_existing;
+        }
+        Ok(())
+    }
+
+    /// 检查会作为命令行参数传给 aria2 的配置
+    pub fn validate(&self) -> Aria2Result<()> {
+        check_range("max-connection-per-server", self.max_connections as u32, 1, 16)?;
+        check_size("min-split-size", &self.split_size)?;
+        check_range("retry-wait", self.retry_wait, 0, 600)?;
+        check_range("connect-timeout", self.connect_timeout, 1, 600)?;
+        if self.poll_interval.is_zero() || self.idle_poll_interval.is_zero() {
+            return Err(Aria2Error::ConfigError("轮询间隔必须大于 0".to_string()));
+        }
+        if let Some(level) = &self.bt_min_crypto_level {
+            if level != "plain" && level != "arc4" {
+                return Err(Aria2Error::ConfigError(format!("bt_min_crypto_level 只能是 plain 或 arc4: {}", level)));
+            }
+        }
+        if let Some(size) = &self.rpc_max_request_size {
+            check_size("rpc-max-request-size", size)?;
+        }
+        for window in &self.quiet_hours {
+            if parse_time_of_day(&window.start).is_none() || parse_time_of_day(&window.end).is_none() {
+                return Err(Aria2Error::ConfigError(format!("静默时段的时刻格式应为 HH:MM: {}-{}", window.start, window.end)));
+            }
+        }
+        if self.no_netrc && self.netrc_path.is_some() {
+            return Err(Aria2Error::ConfigError("同时设置了 netrc_path 和 no_netrc".to_string()));
+        }
+        // 这些参数由本库管理，通过 extra_args 覆盖会导致 RPC 连接不上或进程被重复拉起
+        const MANAGED_ARGS: [&str; 8] = [
+            "--enable-rpc", "--rpc-listen-port", "--rpc-secret", "--daemon", "--dir",
+            "--rpc-listen-all", "--rpc-allow-origin-all", "--rpc-max-request-size",
+        ];
+        for arg in &self.extra_args {
+            let name = arg.split('=').next().unwrap_or_default();
+            if !arg.starts_with("--") {
+                return Err(Aria2Error::ConfigError(format!("额外参数应以 -- 开头: {}", arg)));
+            }
+            if MANAGED_ARGS.contains(&name) {
+                return Err(Aria2Error::ConfigError(format!("{} 由配置字段管理，不能通过额外参数设置", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// [`Aria2Config`] 的构建器
+#[derive(Debug, Clone)]
+pub struct Aria2ConfigBuilder {
+    config: Aria2Config,
+}
+
+impl Aria2ConfigBuilder {
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    pub fn port_policy(mut self, policy: PortPolicy) -> Self {
+        self.config.port_policy = policy;
+        self
+    }
+
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.config.secret = Some(secret.into());
+        self
+    }
+
+    pub fn download_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.download_dir = dir.into();
+        self
+    }
+
+    pub fn aria2_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.aria2_path = path.into();
+        self
+    }
+
+    pub fn max_connections(mut self, max_connections: u8) -> Self {
+        self.config.max_connections = max_connections;
+        self
+    }
+
+    /// 追加一个传给 aria2 的命令行参数
+    pub fn extra_arg(mut self, arg: impl Into<String>) -> Self {
+        self.config.extra_args.push(arg.into());
+        self
+    }
+
+    /// 检查配置并返回，启动任何进程之前发现问题
+    ///
+    /// 除 [`Aria2Config::validate`] 的检查外，还会检查端口范围，并创建下载目录确认可写。
+    pub fn build(self) -> Aria2Result<Aria2Config> {
+        let config = self.config;
+        config.validate()?;
+
+        if config.port < 1024 {
+            return Err(Aria2Error::PortError(format!("端口应在 1024–65535 之间: {}", config.port)));
+        }
+
+        let dir = &config.download_dir;
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Aria2Error::ConfigError(format!("无法创建下载目录 {}: {}", dir.display(), e)))?;
+        let probe = dir.join(".burncloud-write-test");
+        std::fs::write(&probe, b"")
+            .map_err(|e| Aria2Error::ConfigError(format!("下载目录不可写 {}: {}", dir.display(), e)))?;
+        let _ = std::fs::remove_file(&probe);
+
+        Ok(config)
+    }
+}
+
+/// 命名的限速方案，包括总下载/上传速度上限和同时下载的任务数
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpeedProfile {
+    pub name: String,
+    /// 总下载速度上限（字节/秒），0 表示不限制
+    #[serde(default)]
+    pub max_download: u64,
+    /// 总上传速度上限（字节/秒），0 表示不限制
+    #[serde(default)]
+    pub max_upload: u64,
+    /// 同时下载的任务数，None 时使用首次切换方案前 aria2 的设置
+    #[serde(default)]
+    pub max_concurrent: Option<u32>,
+}
+
+impl SpeedProfile {
+    pub fn new(name: impl Into<String>, max_download: u64, max_upload: u64, max_concurrent: Option<u32>) -> Self {
+        Self { name: name.into(), max_download, max_upload, max_concurrent }
+    }
+
+    /// 不限速
+    pub fn unlimited() -> Self {
+        Self::new("Unlimited", 0, 0, None)
+    }
+
+    /// 后台下载：限速并减少同时下载的任务，不影响前台使用网络
+    pub fn background() -> Self {
+        Self::new("Background", 1024 * 1024, 256 * 1024, Some(2))
+    }
+
+    /// 夜间：不限速并增加同时下载的任务
+    pub fn night() -> Self {
+        Self::new("Night", 0, 0, Some(10))
+    }
+
+    /// 内置的方案：Unlimited、Background、Night
+    pub fn presets() -> Vec<Self> {
+        vec![Self::unlimited(), Self::background(), Self::night()]
+    }
+
+    #[cfg(feature = "daemon")]
+    fn global_options(&self) -> HashMap<String, String> {
+        let mut options = HashMap::from([
+            ("max-overall-download-limit".to_string(), self.max_download.to_string()),
+            ("max-overall-upload-limit".to_string(), self.max_upload.to_string()),
+        ]);
+        if let Some(max_concurrent) = self.max_concurrent {
+            options.insert("max-concurrent-downloads".to_string(), max_concurrent.to_string());
+        }
+        options
+    }
+}
+
+/// 每天暂停全部下载的时间段，例如现场演示期间
+///
+/// 时刻为 "HH:MM" 格式，按 UTC 加上 `utc_offset_minutes` 计算；结束早于开始时表示跨过午夜。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+    /// 与 UTC 的时差（分钟），如北京时间为 480
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+impl QuietHours {
+    pub fn new(start: impl Into<String>, end: impl Into<String>, utc_offset_minutes: i32) -> Self {
+        Self { start: start.into(), end: end.into(), utc_offset_minutes }
+    }
+
+    /// 给定的 Unix 时间（秒）是否处于该时间段内，时刻格式错误时返回 false
+    pub fn contains(&self, unix_secs: u64) -> bool {
+        let (Some(start), Some(end)) = (parse_time_of_day(&self.start), parse_time_of_day(&self.end)) else {
+            return false;
+        };
+        let local = unix_secs as i64 + self.utc_offset_minutes as i64 * 60;
+        let minute = (local.rem_euclid(86400) / 60) as u32;
+        if start <= end {
+            start <= minute && minute < end
+        } else {
+            minute >= start || minute < end
+        }
+    }
+
+    /// 从给定的 Unix 时间（秒）到下一个开始或结束时刻的秒数
+    ///
+    /// 正好处于某个时刻时返回到下一个时刻的间隔；时刻格式错误或开始等于结束（从不生效）时返回 None。
+    pub fn next_boundary(&self, unix_secs: u64) -> Option<u64> {
+        let (start, end) = (parse_time_of_day(&self.start)?, parse_time_of_day(&self.end)?);
+        if start == end {
+            return None;
+        }
+        let local = (unix_secs as i64 + self.utc_offset_minutes as i64 * 60).rem_euclid(86400);
+        [start, end].into_iter()
+            .map(|minute| match (minute as i64 * 60 - local).rem_euclid(86400) {
+                0 => 86400,
+                secs => secs as u64,
+            })
+            .min()
+    }
+}
+
+/// 把 "HH:MM" 解析为当天的分钟数
+fn parse_time_of_day(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// RPC 端口的选择策略
+///
+/// 实际使用的端口可通过 [`Aria2Daemon::port`] 或 [`Aria2Manager::rpc_port`] 获取。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortPolicy {
+    /// 只使用配置的端口，被占用时启动失败
+    FixedOrFail,
+    /// 从配置的端口开始向上查找，最多再尝试 `max` 个端口
+    AutoIncrement { max: u16 },
+    /// 首选端口被占用时由系统分配一个空闲端口
+    RandomEphemeral,
+}
+
+impl Default for PortPolicy {
+    fn default() -> Self {
+        Self::AutoIncrement { max: 100 }
+    }
+}
+
+impl PortPolicy {
+    /// 按策略选择端口，`preferred` 可用时总是使用它
+    #[cfg(feature = "daemon")]
+    pub fn select(&self, preferred: u16) -> Aria2Result<u16> {
+        if check_port_available(preferred) {
+            return Ok(preferred);
+        }
+        match *self {
+            Self::FixedOrFail => Err(Aria2Error::PortError(format!("端口 {} 已被占用", preferred))),
+            Self::AutoIncrement { max } => (preferred..=preferred.saturating_add(max))
+                .find(|&port| check_port_available(port))
+                .ok_or_else(|| Aria2Error::PortError(format!("{} 之后的 {} 个端口都已被占用", preferred, max))),
+            Self::RandomEphemeral => std::net::TcpListener::bind(("127.0.0.1", 0))
+                .and_then(|listener| listener.local_addr())
+                .map(|addr| addr.port())
+                .map_err(|e| Aria2Error::PortError(format!("无法分配临时端口: {}", e))),
+        }
+    }
+}
+
+impl Default for Aria2Config {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            port_policy: PortPolicy::default(),
+            secret: None,
+            download_dir: std::env::current_dir().unwrap_or_default().join("downloads"),
+            max_connections: 16,
+            split_size: "1M".to_string(),
+            aria2_path: get_burncloud_dir().join("aria2c.exe"),
+            max_tries: 5,
+            retry_wait: 3,
+            connect_timeout: 60,
+            http_accept_gzip: false,
+            check_certificate: true,
+            ca_certificate: None,
+            netrc_path: None,
+            no_netrc: false,
+            cookies_file: None,
+            user_agent: None,
+            referer: None,
+            bt_require_crypto: false,
+            bt_min_crypto_level: None,
+            bt_max_peers: None,
+            bt_trackers: Vec::new(),
+            bt_tracker_list_url: None,
+            retry_on_reconnect: true,
+            kill_existing: true,
+            max_restarts: None,
+            poll_interval: Duration::from_secs(1),
+            idle_poll_interval: Duration::from_secs(60),
+            idle_shutdown: None,
+            history_file: None,
+            stats_file: None,
+            slow_rpc_threshold: Some(DEFAULT_SLOW_RPC_THRESHOLD),
+            rpc_http: HttpConfig::rpc(),
+            rpc_dump_capacity: 0,
+            rpc_rate_limit: None,
+            event_log: None,
+            on_download_complete: None,
+            on_download_error: None,
+            rpc_listen_all: false,
+            rpc_allow_origin_all: false,
+            rpc_max_request_size: None,
+            speed_profiles: SpeedProfile::presets(),
+            quiet_hours: Vec::new(),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownloadOptions {
+    /// 任务 GID，未指定时由 URL 和存储路径确定性生成（见 [`task_gid`]）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out: Option<String>,
+    #[serde(default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub split: Option<u8>,
+    #[serde(rename = "max-connection-per-server", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub max_connection_per_server: Option<u8>,
+    #[serde(rename = "continue", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub continue_download: Option<bool>,
+    /// 速度低于该值（如 "10K"）时中止下载，需配合 `timeout` 使用
+    #[serde(rename = "lowest-speed-limit", skip_serializing_if = "Option::is_none")]
+    pub lowest_speed_limit: Option<String>,
+    /// 超时秒数
+    #[serde(default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+    /// 最大重试次数，0 表示无限重试
+    #[serde(rename = "max-tries", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub max_tries: Option<u32>,
+    /// 重试间隔秒数
+    #[serde(rename = "retry-wait", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub retry_wait: Option<u32>,
+    /// 建立连接的超时秒数
+    #[serde(rename = "connect-timeout", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u32>,
+    /// 发送 Accept-Encoding: deflate, gzip 并自动解压响应
+    #[serde(rename = "http-accept-gzip", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub http_accept_gzip: Option<bool>,
+    /// 不使用 .netrc 中的凭据
+    #[serde(rename = "no-netrc", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub no_netrc: Option<bool>,
+    #[serde(rename = "user-agent", skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,
+    /// 追加的 BitTorrent tracker，逗号分隔
+    #[serde(rename = "bt-tracker", skip_serializing_if = "Option::is_none")]
+    pub bt_tracker: Option<String>,
+    /// SFTP 主机公钥校验，格式为 `<类型>=<摘要>`，如 "sha-1=b030503d4de4539dc7885e6f0f5e256704edf4c3"
+    ///
+    /// aria2 的 SFTP 只支持密码认证，凭据可放在 URL 或 .netrc 中，不支持私钥。
+    #[serde(rename = "ssh-host-key-md", skip_serializing_if = "Option::is_none")]
+    pub ssh_host_key_md: Option<String>,
+    #[serde(rename = "bt-prioritize-piece", skip_serializing_if = "Option::is_none")]
+    pub bt_prioritize_piece: Option<String>,
+    #[serde(rename = "stream-piece-selector", skip_serializing_if = "Option::is_none")]
+    pub stream_piece_selector: Option<String>,
+    /// 要下载的文件序号（从 1 开始，逗号分隔，如 "1,3-5"），仅对 BitTorrent/Metalink 有效
+    #[serde(rename = "select-file", skip_serializing_if = "Option::is_none")]
+    pub select_file: Option<String>,
+    /// 文件校验和，格式为 `<类型>=<摘要>`，如 "sha-256=..."，下载完成后由 aria2 校验
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// 校验已有文件：目标文件已完整存在且校验和一致时直接完成，不一致时重新下载而不是续传
+    #[serde(rename = "check-integrity", default, with = "option_as_string", skip_serializing_if = "Option::is_none")]
+    pub check_integrity: Option<bool>,
+    /// 流式模式：优先下载首尾分片并按顺序选择分片，便于边下边播
+    #[serde(skip)]
+    pub streaming: bool,
+    /// 插入等待队列的位置，0 表示队首；未指定时追加到队尾
+    #[serde(skip)]
+    pub position: Option<u32>,
+}
+
+/// 以秒数（可带小数）表示的时间间隔
+mod duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+    }
+}
+
+mod option_duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<f64>::deserialize(deserializer)?
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// aria2 的 RPC 只接受字符串形式的选项值，数值和布尔值会被静默忽略
+mod option_as_string {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(v) => serializer.serialize_str(&v.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let raw = match Option::<Value>::deserialize(deserializer)? {
+            None | Some(Value::Null) => return Ok(None),
+            Some(Value::String(s)) => s,
+            Some(other) => other.to_string(),
+        };
+        raw.parse().map(Some).map_err(serde::de::Error::custom)
+    }
+}
+
+impl DownloadOptions {
+    /// 将便捷开关展开为 aria2 实际识别的选项
+    fn resolve(mut self) -> Self {
+        if self.streaming {
+            self.bt_prioritize_piece.get_or_insert_with(|| "head,tail".to_string());
+            self.stream_piece_selector.get_or_insert_with(|| "inorder".to_string());
+        }
+        // 提供了校验和时先校验已有文件，避免重复下载或在错误的文件后面续传
+        if self.checksum.is_some() {
+            self.check_integrity.get_or_insert(true);
+        }
+        self
+    }
+
+    /// 按 aria2 的取值范围检查选项，在发送 RPC 之前给出明确的错误信息
+    pub fn validate(&self) -> Aria2Result<()> {
+        if self.split == Some(0) {
+            return Err(Aria2Error::ConfigError("split 必须大于 0".to_string()));
+        }
+        if let Some(n) = self.max_connection_per_server {
+            check_range("max-connection-per-server", n as u32, 1, 16)?;
+        }
+        if let Some(limit) = &self.lowest_speed_limit {
+            check_size("lowest-speed-limit", limit)?;
+        }
+        if let Some(timeout) = self.timeout {
+            check_range("timeout", timeout, 1, 600)?;
+        }
+        if let Some(wait) = self.retry_wait {
+            check_range("retry-wait", wait, 0, 600)?;
+        }
+        if let Some(timeout) = self.connect_timeout {
+            check_range("connect-timeout", timeout, 1, 600)?;
+        }
+        if let Some(checksum) = &self.checksum {
+            let valid = checksum.split_once('=').is_some_and(|(kind, digest)| {
+                ["md5", "adler32", "sha-1", "sha-224", "sha-256", "sha-384", "sha-512"].contains(&kind.to_ascii_lowercase().as_str())
+                    && !digest.is_empty()
+                    && digest.chars().all(|c| c.is_ascii_hexdigit())
+            });
+            if !valid {
+                return Err(Aria2Error::ConfigError(format!("无效的 checksum，应为 <类型>=<十六进制摘要>: {}", checksum)));
+            }
+        }
+        if let Some(selection) = &self.select_file {
+            let valid = selection.split(',').all(|part| {
+                let (start, end) = part.split_once('-').unwrap_or((part, part));
+                matches!((start.trim().parse::<usize>(), end.trim().parse::<usize>()), (Ok(a), Ok(b)) if a >= 1 && a <= b)
+            });
+            if !valid {
+                return Err(Aria2Error::ConfigError(format!("无效的 select-file，应为从 1 开始的序号或范围，如 \"1,3-5\": {}", selection)));
+            }
+        }
+        if let Some(selector) = &self.stream_piece_selector {
+            if !["default", "inorder", "random", "geom"].contains(&selector.as_str()) {
+                return Err(Aria2Error::ConfigError(format!("无效的 stream-piece-selector，可选 default、inorder、random、geom: {}", selector)));
+            }
+        }
+        if let Some(priority) = &self.bt_prioritize_piece {
+            for part in priority.split(',') {
+                let (kind, size) = part.split_once('=').map_or((part, None), |(k, v)| (k, Some(v)));
+                if kind != "head" && kind != "tail" {
+                    return Err(Aria2Error::ConfigError(format!("无效的 bt-prioritize-piece，应为 head[=SIZE],tail[=SIZE]: {}", priority)));
+                }
+                if let Some(size) = size {
+                    check_size("bt-prioritize-piece", size)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 检查数值选项是否在 aria2 允许的范围内
+fn check_range(name: &str, value: u32, min: u32, max: u32) -> Aria2Result<()> {
+    if value < min || value > max {
+        return Err(Aria2Error::ConfigError(format!("{} 必须在 {}–{} 之间，当前为 {}", name, min, max, value)));
+    }
+    Ok(())
+}
+
+/// 检查大小或速度字符串，aria2 只接受整数加可选的 K/M 后缀，如 "1M"、"500K"
+fn check_size(name: &str, value: &str) -> Aria2Result<()> {
+    let digits = value.strip_suffix(['K', 'k', 'M', 'm']).unwrap_or(value);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Aria2Error::ConfigError(format!("{} 应为整数加可选的 K/M 后缀（如 \"1M\"）: {}", name, value)));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadStatus {
+    pub gid: String,
+    pub status: String,
+    #[serde(rename = "totalLength")]
+    pub total_length: String,
+    #[serde(rename = "completedLength")]
+    pub completed_length: String,
+    #[serde(rename = "downloadSpeed")]
+    pub download_speed: String,
+    #[serde(rename = "uploadLength", default)]
+    pub upload_length: String,
+    #[serde(rename = "uploadSpeed", default)]
+    pub upload_speed: String,
+    /// 当前连接的对等节点/服务器数
+    #[serde(default)]
+    pub connections: String,
+    /// 已连接的做种者数量，仅 BitTorrent 下载有该字段
+    #[serde(rename = "numSeeders", default)]
+    pub num_seeders: Option<String>,
+    /// aria2 的错误码，仅已停止的任务有该字段
+    #[serde(rename = "errorCode", default)]
+    pub error_code: Option<String>,
+    #[serde(rename = "errorMessage", default)]
+    pub error_message: Option<String>,
+    /// 已校验的字节数，仅在校验文件时有该字段
+    #[serde(rename = "verifiedLength", default)]
+    pub verified_length: Option<String>,
+}
+
+impl DownloadStatus {
+    /// 完成百分比（0-100），总大小未知时返回 None
+    pub fn percent(&self) -> Option<f64> {
+        let total: u64 = self.total_length.parse().ok()?;
+        let completed: u64 = self.completed_length.parse().ok()?;
+        if total == 0 {
+            return None;
+        }
+        Some(completed as f64 * 100.0 / total as f64)
+    }
+
+    /// 按当前下载速度估算的剩余时间，大小未知或速度为 0 时返回 None
+    pub fn eta(&self) -> Option<Duration> {
+        let total: u64 = self.total_length.parse().ok()?;
+        let completed: u64 = self.completed_length.parse().ok()?;
+        if total == 0 {
+            return None;
+        }
+        format::eta(total.saturating_sub(completed), self.download_speed.parse().ok()?)
+    }
+
+    /// 分享率（已上传 / 已下载），尚未下载任何数据时返回 None
+    pub fn share_ratio(&self) -> Option<f64> {
+        let uploaded: u64 = self.upload_length.parse().ok()?;
+        let completed: u64 = self.completed_length.parse().ok()?;
+        if completed == 0 {
+            return None;
+        }
+        Some(uploaded as f64 / completed as f64)
+    }
+
+    /// 任务失败的原因，任务没有出错时返回 None
+    pub fn task_error(&self) -> Option<Aria2TaskError> {
+        self.error_code.as_deref().and_then(Aria2TaskError::from_code)
+    }
+}
+
+/// aria2 任务的错误码（errorCode，对应 aria2 文档中的退出状态 1–32）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aria2TaskError {
+    /// 1：未知错误
+    Unknown,
+    /// 2：超时
+    Timeout,
+    /// 3：资源不存在
+    NotFound,
+    /// 4：资源不存在的次数达到 --max-file-not-found
+    TooManyNotFound,
+    /// 5：速度低于 --lowest-speed-limit
+    TooSlow,
+    /// 6：网络错误
+    NetworkError,
+    /// 7：关闭时仍有未完成的下载
+    Unfinished,
+    /// 8：服务器不支持断点续传，但续传是完成下载所必需的
+    ResumeNotSupported,
+    /// 9：磁盘空间不足
+    DiskFull,
+    /// 10：分片长度与 .aria2 控制文件中的不一致
+    PieceLengthMismatch,
+    /// 11：相同的文件正在下载
+    DuplicateDownload,
+    /// 12：相同 info hash 的种子正在下载
+    DuplicateInfoHash,
+    /// 13：文件已存在
+    FileExists,
+    /// 14：重命名文件失败
+    RenameFailed,
+    /// 15：无法打开已有文件
+    OpenFileFailed,
+    /// 16：无法创建或截断文件
+    CreateFileFailed,
+    /// 17：文件读写错误
+    FileIoError,
+    /// 18：无法创建目录
+    CreateDirFailed,
+    /// 19：域名解析失败
+    NameResolutionFailed,
+    /// 20：Metalink 文件解析失败
+    MetalinkParseError,
+    /// 21：FTP 命令执行失败
+    FtpCommandFailed,
+    /// 22：HTTP 响应头错误或不符合预期
+    BadHttpResponse,
+    /// 23：重定向次数过多
+    TooManyRedirects,
+    /// 24：HTTP 认证失败
+    HttpAuthFailed,
+    /// 25：bencode 格式解析失败（通常是 .torrent 文件损坏）
+    BencodeParseError,
+    /// 26：.torrent 文件损坏或缺少必要信息
+    TorrentCorrupted,
+    /// 27：磁力链接格式错误
+    BadMagnetUri,
+    /// 28：选项错误或不被识别
+    BadOption,
+    /// 29：服务器暂时过载或维护中
+    ServerOverloaded,
+    /// 30：无法解析 JSON-RPC 请求
+    RpcParseError,
+    /// 32：校验和验证失败
+    ChecksumMismatch,
+    /// 文档中没有的错误码
+    Other(u32),
+}
+
+impl Aria2TaskError {
+    /// 解析 aria2 返回的 errorCode，"0"（成功）或无法解析时返回 None
+    pub fn from_code(code: &str) -> Option<Self> {
+        let error = match code.parse::<u32>().ok()? {
+            0 => return None,
+            1 => Self::Unknown,
+            2 => Self::Timeout,
+            3 => Self::NotFound,
+            4 => Self::TooManyNotFound,
+            5 => Self::TooSlow,
+            6 => Self::NetworkError,
+            7 => Self::Unfinished,
+            8 => Self::ResumeNotSupported,
+            9 => Self::DiskFull,
+            10 => Self::PieceLengthMismatch,
+            11 => Self::DuplicateDownload,
+            12 => Self::DuplicateInfoHash,
+            13 => Self::FileExists,
+            14 => Self::RenameFailed,
+            15 => Self::OpenFileFailed,
+            16 => Self::CreateFileFailed,
+            17 => Self::FileIoError,
+            18 => Self::CreateDirFailed,
+            19 => Self::NameResolutionFailed,
+            20 => Self::MetalinkParseError,
+            21 => Self::FtpCommandFailed,
+            22 => Self::BadHttpResponse,
+            23 => Self::TooManyRedirects,
+            24 => Self::HttpAuthFailed,
+            25 => Self::BencodeParseError,
+            26 => Self::TorrentCorrupted,
+            27 => Self::BadMagnetUri,
+            28 => Self::BadOption,
+            29 => Self::ServerOverloaded,
+            30 => Self::RpcParseError,
+            32 => Self::ChecksumMismatch,
+            other => Self::Other(other),
+        };
+        Some(error)
+    }
+
+    /// 与网络连接相关的错误，网络恢复后重试通常能成功
+    pub fn is_network_related(&self) -> bool {
+        matches!(self, Self::Timeout | Self::NetworkError | Self::NameResolutionFailed)
+    }
+}
+
+impl Aria2TaskError {
+    /// 用指定语言描述错误，`Display` 使用中文
+    pub fn localized(&self, lang: Language) -> String {
+        let (chinese, english) = match self {
+            Self::Unknown => ("未知错误", "Unknown error"),
+            Self::Timeout => ("连接超时", "Connection timed out"),
+            Self::NotFound => ("资源不存在", "Resource not found"),
+            Self::TooManyNotFound => ("多次找不到资源", "Resource not found too many times"),
+            Self::TooSlow => ("下载速度过慢", "Download too slow"),
+            Self::NetworkError => ("网络错误", "Network error"),
+            Self::Unfinished => ("下载未完成", "Download unfinished"),
+            Self::ResumeNotSupported => ("服务器不支持断点续传", "Server does not support resuming"),
+            Self::DiskFull => ("磁盘空间不足", "Not enough disk space"),
+            Self::PieceLengthMismatch => ("分片长度与控制文件不一致", "Piece length differs from control file"),
+            Self::DuplicateDownload => ("相同的文件正在下载", "Same file is already downloading"),
+            Self::DuplicateInfoHash => ("相同的种子正在下载", "Same torrent is already downloading"),
+            Self::FileExists => ("文件已存在", "File already exists"),
+            Self::RenameFailed => ("重命名文件失败", "Failed to rename file"),
+            Self::OpenFileFailed => ("无法打开文件", "Failed to open file"),
+            Self::CreateFileFailed => ("无法创建文件", "Failed to create file"),
+            Self::FileIoError => ("文件读写错误", "File I/O error"),
+            Self::CreateDirFailed => ("无法创建目录", "Failed to create directory"),
+            Self::NameResolutionFailed => ("域名解析失败", "Name resolution failed"),
+            Self::MetalinkParseError => ("Metalink 解析失败", "Failed to parse Metalink"),
+            Self::FtpCommandFailed => ("FTP 命令失败", "FTP command failed"),
+            Self::BadHttpResponse => ("HTTP 响应异常", "Unexpected HTTP response"),
+            Self::TooManyRedirects => ("重定向次数过多", "Too many redirects"),
+            Self::HttpAuthFailed => ("HTTP 认证失败", "HTTP authentication failed"),
+            Self::BencodeParseError => ("种子文件格式错误", "Malformed torrent file"),
+            Self::TorrentCorrupted => ("种子文件损坏", "Corrupted torrent file"),
+            Self::BadMagnetUri => ("磁力链接格式错误", "Malformed magnet link"),
+            Self::BadOption => ("选项错误", "Invalid option"),
+            Self::ServerOverloaded => ("服务器繁忙", "Server busy"),
+            Self::RpcParseError => ("RPC 请求解析失败", "Failed to parse RPC request"),
+            Self::ChecksumMismatch => ("校验和不一致", "Checksum mismatch"),
+            Self::Other(code) => {
+                return match lang {
+                    Language::Chinese => format!("错误码 {}", code),
+                    Language::English => format!("Error code {}", code),
+                };
+            }
+        };
+        match lang {
+            Language::Chinese => chinese.to_string(),
+            Language::English => english.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Aria2TaskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localized(Language::Chinese))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GlobalStat {
+    #[serde(rename = "downloadSpeed")]
+    pub download_speed: String,
+    #[serde(rename = "uploadSpeed", default)]
+    pub upload_speed: String,
+    #[serde(rename = "numActive")]
+    pub num_active: String,
+    #[serde(rename = "numWaiting")]
+    pub num_waiting: String,
+    #[serde(rename = "numStopped", default)]
+    pub num_stopped: String,
+}
+
+/// 所有活跃任务的汇总进度
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GlobalProgress {
+    pub total_bytes: u64,
+    pub downloaded_bytes: u64,
+    /// 总下载速度（字节/秒）
+    pub download_speed: u64,
+    /// 总上传速度（字节/秒）
+    pub upload_speed: u64,
+    pub num_active: usize,
+}
+
+impl GlobalProgress {
+    /// 总体完成百分比，总大小未知时为 0
+    pub fn percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            self.downloaded_bytes as f64 * 100.0 / self.total_bytes as f64
+        }
+    }
+
+    /// 按总下载速度估算的剩余时间，速度为 0 时返回 None
+    pub fn eta(&self) -> Option<Duration> {
+        format::eta(self.total_bytes.saturating_sub(self.downloaded_bytes), self.download_speed)
+    }
+}
+
+/// `probe` 的结果
+#[derive(Debug, Clone)]
+pub struct ProbeInfo {
+    /// 文件大小（字节），服务器未提供时为 None
+    pub content_length: Option<u64>,
+    /// 服务器是否支持断点续传（Accept-Ranges: bytes）
+    pub resumable: bool,
+    /// Content-Disposition 中给出的文件名
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VersionInfo {
+    pub version: String,
+    #[serde(rename = "enabledFeatures")]
+    pub enabled_features: Vec<String>,
+}
+
+/// 查询任务状态时向 aria2 请求的字段，与 [`DownloadStatus`] 一一对应
+///
+/// 不请求 files、bitfield 等字段，BT 任务的文件列表可能有上千项，每轮轮询都取回代价很高。
+const STATUS_KEYS: &[&str] = &[
+    "gid", "status", "totalLength", "completedLength", "downloadSpeed", "uploadLength", "uploadSpeed",
+    "connections", "numSeeders", "errorCode", "errorMessage", "verifiedLength",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileInfo {
+    pub path: String,
+    pub uris: Vec<UriInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UriInfo {
+    pub uri: String,
+    pub status: String,
+}
+
+#[cfg(feature = "daemon")]
+pub struct Aria2Instance {
+    pub process: Child,
+    pub port: u16,
+    pub config: Aria2Config,
+}
+
+#[cfg(feature = "daemon")]
+impl Aria2Instance {
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.process.try_wait(), Ok(None))
+    }
+
+    pub fn kill(&mut self) -> Aria2Result<()> {
+        self.process.kill()
+            .map_err(|e| Aria2Error::ProcessError(e.to_string()))?;
+        self.process.wait()
+            .map_err(|e| Aria2Error::ProcessError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Aria2 下载功能
+// ============================================================================
+
+/// 下载 aria2 程序所处的阶段
+#[cfg(feature = "daemon")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootstrapPhase {
+    /// 下载压缩包，进度单位为字节
+    Downloading,
+    /// 从压缩包中解压 aria2c.exe，进度单位为字节
+    Extracting,
+    /// 确认解压出的程序大小与压缩包中的记录一致，进度单位为字节
+    Verifying,
+}
+
+/// 下载 aria2 二进制文件
+#[cfg(feature = "daemon")]
+pub async fn download_aria2() -> Aria2Result<PathBuf> {
+    download_aria2_with_progress(|_, _, _| {}).await
+}
+
+/// 下载 aria2 二进制文件，并通过 `progress(阶段, 已完成, 总量)` 报告进度
+///
+/// 总量未知时为 0。慢速网络上下载可能持续较长时间，宿主程序可以据此显示安装进度。
+#[cfg(feature = "daemon")]
+pub async fn download_aria2_with_progress<F>(mut progress: F) -> Aria2Result<PathBuf>
+where
+    F: FnMut(BootstrapPhase, u64, u64),
+{
+    let client = try_http_client()?;
+
+    let target_dir = get_burncloud_dir();
+    std::fs::create_dir_all(&target_dir)
+        .map_err(|e| Aria2Error::DownloadError(format!("创建目录失败: {}", e)))?;
+
+    let zip_path = target_dir.join("aria2.zip");
+    let exe_path = target_dir.join("aria2c.exe");
+
+    // 如果 exe 已存在，直接返回
+    if exe_path.exists() {
+        return Ok(exe_path);
+    }
+
+    // 尝试主链接下载
+    let mut downloading = |done, total| progress(BootstrapPhase::Downloading, done, total);
+    match download_file(&client, ARIA2_MAIN_URL, &zip_path, &mut downloading).await {
+        Ok(_) => tracing::info!("从主链接下载 aria2 成功"),
+        Err(e) => {
+            tracing::warn!(error = %e, "主链接下载失败，尝试备用链接");
+            download_file(&client, ARIA2_BACKUP_URL, &zip_path, &mut downloading).await
+                .map_err(|e| Aria2Error::DownloadError(format!("所有下载链接均失败: {}", e)))?;
+            tracing::info!("从备用链接下载 aria2 成功");
+        }
+    }
+
+    // 先解压到临时文件，校验通过后再改名，中断的解压不会留下看似可用的 aria2c.exe
+    let temp_path = target_dir.join("aria2c.exe.tmp");
+    let extracted = extract_aria2(&zip_path, &temp_path, &mut |done, total| {
+        progress(BootstrapPhase::Extracting, done, total)
+    });
+
+    // 删除 ZIP 文件，续传得到的文件损坏时下次也会重新下载
+    let _ = std::fs::remove_file(&zip_path);
+    let expected = extracted.inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp_path);
+    })?;
+
+    // 内容的 CRC 已在解压时由 zip 校验，这里确认写入磁盘的大小完整
+    progress(BootstrapPhase::Verifying, 0, expected);
+    let size = std::fs::metadata(&temp_path).map(|m| m.len()).unwrap_or(0);
+    if size != expected {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(Aria2Error::DownloadError(format!(
+            "aria2c.exe 不完整: 应为 {} 字节，实际 {} 字节", expected, size,
+        )));
+    }
+    std::fs::rename(&temp_path, &exe_path)
+        .map_err(|e| Aria2Error::DownloadError(format!("重命名 {} 失败: {}", temp_path.display(), e)))?;
+    progress(BootstrapPhase::Verifying, size, expected);
+    Ok(exe_path)
+}
+
+/// 下载中断后连续重试的最大次数，每次续传有进展都会重新计数
+#[cfg(feature = "daemon")]
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+/// 单次下载请求的总时长上限，代替共享客户端较短的超时，避免慢速网络上的下载被反复中断
+#[cfg(feature = "daemon")]
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(3600);
+/// 超过该时间没有收到任何数据时断开连接并续传
+#[cfg(feature = "daemon")]
+const DOWNLOAD_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 未完成下载的校验信息，保存在 `.part` 文件旁，续传前确认服务器上仍是同一个文件
+#[cfg(feature = "daemon")]
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PartialDownload {
+    /// 文件总大小
+    total: Option<u64>,
+    /// 强 ETag 或 Last-Modified，续传时作为 If-Range 发送
+    validator: Option<String>,
+}
+
+/// 把 `url` 流式下载到 `path`
+///
+/// 数据先写入 `<path>.part`，连接中断时用 Range 请求从已写入的位置继续下载。
+/// 放弃时保留 `.part` 文件，下次下载时只有服务器确认文件没有变化（If-Range 和总大小一致）才会续传，
+/// 换用其他链接或文件已更新时从头下载。
+/// 每写入一块数据调用一次 `progress(已下载, 总大小)`，总大小未知时为 0。
+#[cfg(feature = "daemon")]
+pub(crate) async fn download_file(
+    client: &Client,
+    url: &str,
+    path: &Path,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Aria2Result<()> {
+    let sibling = |suffix: &str| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let part = sibling(".part");
+    let meta = sibling(".part.json");
+    let written = |part: &Path| std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+
+    let mut failures = 0;
+    loop {
+        let before = written(&part);
+        match fetch_remaining(client, url, &part, &meta, progress).await {
+            Ok(()) => break,
+            Err((e, retryable)) => {
+                let offset = written(&part);
+                if offset > before {
+                    failures = 0;
+                }
+                failures += 1;
+                if !retryable || failures >= DOWNLOAD_ATTEMPTS {
+                    return Err(e);
+                }
+                tracing::warn!(url, offset, error = %e, "下载中断，准备续传");
+                tokio::time::sleep(Duration::from_secs(failures as u64)).await;
+            }
+        }
+    }
+
+    let _ = std::fs::remove_file(&meta);
+    std::fs::rename(&part, path)
+        .map_err(|e| Aria2Error::DownloadError(format!("重命名 {} 失败: {}", part.display(), e)))
+}
+
+/// 从 `part` 已有的长度开始请求剩余内容并追加写入，错误附带是否值得重试
+#[cfg(feature = "daemon")]
+async fn fetch_remaining(
+    client: &Client,
+    url: &str,
+    part: &Path,
+    meta: &Path,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<(), (Aria2Error, bool)> {
+    use std::io::Write;
+
+    let retry = |e: String| (Aria2Error::DownloadError(e), true);
+    let discard = || {
+        let _ = std::fs::remove_file(part);
+        let _ = std::fs::remove_file(meta);
+    };
+
+    // 没有校验信息的 .part 无法确认来自同一个文件，从头下载
+    let saved: Option<PartialDownload> = std::fs::read(meta).ok()
+        .and_then(|data| serde_json::from_slice(&data).ok());
+    let mut offset = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    if offset > 0 && saved.is_none() {
+        discard();
+        offset = 0;
+    }
+    let saved = saved.unwrap_or_default();
+
+    let mut request = client.get(url).timeout(DOWNLOAD_TIMEOUT);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        if let Some(validator) = &saved.validator {
+            request = request.header(reqwest::header::IF_RANGE, validator);
+        }
+    }
+    let mut response = request.send().await.map_err(|e| retry(e.to_string()))?;
+
+    let status = response.status();
+    // Content-Range: bytes 100-199/200 或 bytes */200
+    let content_range = header_str(response.headers(), reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.strip_prefix("bytes "))
+        .map(|v| {
+            let (range, total) = v.split_once('/').unwrap_or((v, "*"));
+            let start = range.split('-').next().and_then(|s| s.parse::<u64>().ok());
+            (start, total.parse::<u64>().ok())
+        });
+    let total_changed = |total: Option<u64>| saved.total.is_some() && total != saved.total;
+
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        let total = content_range.and_then(|(_, total)| total);
+        if total == Some(offset) && !total_changed(total) {
+            progress(offset, offset);
+            return Ok(());
+        }
+        // 本地文件比服务器上的还大，说明文件已经变化，从头下载
+        discard();
+        return Err(retry("续传位置超出文件大小".to_string()));
+    }
+    if !status.is_success() {
+        let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        return Err((Aria2Error::DownloadError(format!("HTTP错误: {}", status)), retryable));
+    }
+
+    // 服务器忽略 Range 或 If-Range 不匹配（文件已变化）时返回完整内容，从头写入
+    let resumed = offset > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+    if resumed {
+        let (start, total) = content_range.unwrap_or((None, None));
+        if start != Some(offset) || total_changed(total) {
+            discard();
+            return Err(retry("服务器返回的文件与已下载的部分不一致".to_string()));
+        }
+    } else {
+        if offset > 0 {
+            tracing::info!(url, "无法续传，从头下载");
+        }
+        let headers = response.headers();
+        let etag = header_str(headers, reqwest::header::ETAG).filter(|etag| !etag.starts_with("W/"));
+        let partial = PartialDownload {
+            total: response.content_length(),
+            validator: etag.or_else(|| header_str(headers, reqwest::header::LAST_MODIFIED)).map(str::to_string),
+        };
+        let data = serde_json::to_vec(&partial).unwrap_or_default();
+        std::fs::write(meta, data)
+            .map_err(|e| (Aria2Error::DownloadError(format!("写入 {} 失败: {}", meta.display(), e)), false))?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    if resumed {
+        options.append(true);
+    } else {
+        options.write(true).create(true).truncate(true);
+    }
+    let mut file = options.open(part)
+        .map_err(|e| (Aria2Error::DownloadError(format!("打开 {} 失败: {}", part.display(), e)), false))?;
+
+    let mut written = if resumed { offset } else { 0 };
+    let total = match content_range.and_then(|(_, total)| total) {
+        Some(total) if resumed => total,
+        _ => response.content_length().map_or(0, |length| written + length),
+    };
+    progress(written, total);
+    loop {
+        let chunk = tokio::time::timeout(DOWNLOAD_STALL_TIMEOUT, response.chunk()).await
+            .map_err(|_| retry("长时间没有收到数据".to_string()))?
+            .map_err(|e| retry(e.to_string()))?;
+        let Some(chunk) = chunk else { break };
+        file.write_all(&chunk)
+            .map_err(|e| (Aria2Error::DownloadError(format!("写入 {} 失败: {}", part.display(), e)), false))?;
+        written += chunk.len() as u64;
+        progress(written, total);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "daemon")]
+/// 把压缩包中的 aria2c.exe 解压到 `out_path`，返回压缩包中记录的大小
+fn extract_aria2(zip_path: &Path, out_path: &Path, progress: &mut dyn FnMut(u64, u64)) -> Aria2Result<u64> {
+    use std::io::{Read, Write};
+
+    let file = std::fs::File::open(zip_path)
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)
+            .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+
+        if file.name().ends_with("aria2c.exe") {
+            let mut out_file = std::fs::File::create(out_path)
+                .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+            let total = file.size();
+            let mut done = 0;
+            let mut buf = vec![0u8; 64 * 1024];
+            progress(done, total);
+            loop {
+                let n = file.read(&mut buf)
+                    .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+                if n == 0 {
+                    break;
+                }
+                out_file.write_all(&buf[..n])
+                    .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+                done += n as u64;
+                progress(done, total);
+            }
+            return Ok(total);
+        }
+    }
+
+    Err(Aria2Error::DownloadError("ZIP文件中未找到 aria2c.exe".to_string()))
+}
+
+// ============================================================================
+// 下载预检
+// ============================================================================
+
+/// 发送 HEAD 请求探测下载地址的大小、断点续传支持和文件名
+///
+/// 服务器拒绝 HEAD 时退回到只请求第一个字节的 GET。
+pub async fn probe(url: &str) -> Aria2Result<ProbeInfo> {
+    let client = try_http_client()?;
+
+    let head = client.head(url).send().await
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+    if head.status().is_success() {
+        let headers = head.headers();
+        return Ok(ProbeInfo {
+            content_length: header_str(headers, reqwest::header::CONTENT_LENGTH)
+                .and_then(|v| v.parse().ok()),
+            resumable: header_str(headers, reqwest::header::ACCEPT_RANGES)
+                .is_some_and(|v| v.eq_ignore_ascii_case("bytes")),
+            filename: header_str(headers, reqwest::header::CONTENT_DISPOSITION)
+                .and_then(parse_content_disposition),
+        });
+    }
+
+    let response = client.get(url)
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(Aria2Error::DownloadError(format!("HTTP错误: {}", response.status())));
+    }
+
+    let headers = response.headers();
+    let resumable = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let content_length = if resumable {
+        // Content-Range: bytes 0-0/12345
+        header_str(headers, reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|v| v.parse().ok())
+    } else {
+        header_str(headers, reqwest::header::CONTENT_LENGTH).and_then(|v| v.parse().ok())
+    };
+
+    Ok(ProbeInfo {
+        content_length,
+        resumable,
+        filename: header_str(headers, reqwest::header::CONTENT_DISPOSITION)
+            .and_then(parse_content_disposition),
+    })
+}
+
+fn header_str(headers: &reqwest::header::HeaderMap, name: reqwest::header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// 从 Content-Disposition 中解析文件名，优先使用 RFC 5987 的 filename*
+fn parse_content_disposition(value: &str) -> Option<String> {
+    let mut plain = None;
+
+    for part in value.split(';').map(str::trim) {
+        let Some((key, val)) = part.split_once('=') else {
+            continue;
+        };
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                // 形如 UTF-8''%E6%96%87%E4%BB%B6.zip
+                let encoded = val.trim().splitn(3, '\'').nth(2)?;
+                return percent_decode(encoded).filter(|n| !n.is_empty());
+            }
+            "filename" => {
+                plain = Some(val.trim().trim_matches('"').to_string());
+            }
+            _ => {}
+        }
+    }
+
+    plain.filter(|n| !n.is_empty())
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+// ============================================================================
+// 压缩包解压
+// ============================================================================
+
+/// 将下载完成的压缩包解压到目标目录，支持 .zip、.tar、.tar.gz/.tgz
+///
+/// `progress` 以 (已处理字节数, 压缩包总字节数) 的形式回调，返回解压出的文件列表。
+#[cfg(feature = "extract")]
+pub fn extract_archive<F>(archive: &Path, target_dir: &Path, mut progress: F) -> Aria2Result<Vec<PathBuf>>
+where
+    F: FnMut(u64, u64),
+{
+    let name = archive.file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let total = std::fs::metadata(archive)
+        .map_err(|e| Aria2Error::ExtractError(format!("读取压缩包失败: {}", e)))?
+        .len();
+
+    std::fs::create_dir_all(target_dir)
+        .map_err(|e| Aria2Error::ExtractError(format!("创建目录失败: {}", e)))?;
+
+    let file = std::fs::File::open(archive)
+        .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+
+    if name.ends_with(".zip") {
+        extract_zip(file, target_dir, total, &mut progress)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let reader = ProgressReader::new(file, total, &mut progress);
+        extract_tar(flate2::read::GzDecoder::new(reader), target_dir)
+    } else if name.ends_with(".tar") {
+        extract_tar(ProgressReader::new(file, total, &mut progress), target_dir)
+    } else {
+        Err(Aria2Error::ExtractError(format!("不支持的压缩格式: {}", archive.display())))
+    }
+}
+
+#[cfg(feature = "extract")]
+fn extract_zip(file: std::fs::File, target_dir: &Path, total: u64, progress: &mut dyn FnMut(u64, u64)) -> Aria2Result<Vec<PathBuf>> {
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+
+    let mut extracted = Vec::new();
+    let mut processed = 0;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)
+            .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+
+        // 拒绝解压到目标目录之外的条目
+        let relative = entry.enclosed_name()
+            .ok_or_else(|| Aria2Error::ExtractError(format!("非法的条目路径: {}", entry.name())))?
+            .to_path_buf();
+        let out_path = target_dir.join(relative);
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&out_path)
+                .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+        } else {
+            if let Some(parent) = out_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+            }
+            let mut out_file = std::fs::File::create(&out_path)
+                .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+            std::io::copy(&mut entry, &mut out_file)
+                .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+            extracted.push(out_path);
+        }
+
+        processed += entry.compressed_size();
+        progress(processed.min(total), total);
+    }
+
+    Ok(extracted)
+}
+
+#[cfg(feature = "extract")]
+fn extract_tar<R: std::io::Read>(reader: R, target_dir: &Path) -> Aria2Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut extracted = Vec::new();
+
+    let entries = archive.entries()
+        .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+        let relative = entry.path()
+            .map_err(|e| Aria2Error::ExtractError(e.to_string()))?
+            .to_path_buf();
+        let is_file = entry.header().entry_type().is_file();
+
+        // unpack_in 会拒绝解压到目标目录之外的条目
+        let unpacked = entry.unpack_in(target_dir)
+            .map_err(|e| Aria2Error::ExtractError(e.to_string()))?;
+        if !unpacked {
+            return Err(Aria2Error::ExtractError(format!("非法的条目路径: {}", relative.display())));
+        }
+        if is_file {
+            extracted.push(target_dir.join(relative));
+        }
+    }
+
+    Ok(extracted)
+}
+
+/// 统计已读取字节数并回调进度的读取器
+#[cfg(feature = "extract")]
+struct ProgressReader<'a, R> {
+    inner: R,
+    read: u64,
+    total: u64,
+    progress: &'a mut dyn FnMut(u64, u64),
+}
+
+#[cfg(feature = "extract")]
+impl<'a, R> ProgressReader<'a, R> {
+    fn new(inner: R, total: u64, progress: &'a mut dyn FnMut(u64, u64)) -> Self {
+        Self { inner, read: 0, total, progress }
+    }
+}
+
+#[cfg(feature = "extract")]
+impl<R: std::io::Read> std::io::Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        (self.progress)(self.read, self.total);
+        Ok(n)
+    }
+}
+
+// ============================================================================
+// 文件操作
+// ============================================================================
+
+/// 移动文件，跨卷时退回到复制后删除
+///
+/// 如果 `to` 是已存在的目录，则移动到该目录下并保留原文件名。返回最终路径。
+pub fn move_file(from: &Path, to: &Path) -> Aria2Result<PathBuf> {
+    let target = if to.is_dir() {
+        let name = from.file_name()
+            .ok_or_else(|| Aria2Error::FileError(format!("无效的文件路径: {}", from.display())))?;
+        to.join(name)
+    } else {
+        to.to_path_buf()
+    };
+
+    if target.exists() {
+        return Err(Aria2Error::FileError(format!("目标文件已存在: {}", target.display())));
+    }
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| Aria2Error::FileError(format!("创建目录失败: {}", e)))?;
+    }
+
+    if std::fs::rename(from, &target).is_err() {
+        // rename 无法跨卷，复制完成后再删除源文件
+        if let Err(e) = std::fs::copy(from, &target) {
+            let _ = std::fs::remove_file(&target);
+            return Err(Aria2Error::FileError(format!("复制文件失败: {}", e)));
+        }
+        std::fs::remove_file(from)
+            .map_err(|e| Aria2Error::FileError(format!("删除源文件失败: {}", e)))?;
+    }
+
+    Ok(target)
+}
+
+/// 递归收集目录下的 .aria2 控制文件
+fn collect_control_files(dir: &Path, found: &mut Vec<PathBuf>) -> Aria2Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| Aria2Error::FileError(format!("读取目录失败: {}", e)))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_control_files(&path, found)?;
+        } else if path.extension().is_some_and(|ext| ext == "aria2") {
+            found.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// 计算文件的 SHA-256，返回小写十六进制字符串
+#[cfg(feature = "checksum")]
+pub fn sha256_file(path: &Path) -> Aria2Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| Aria2Error::FileError(format!("打开 {} 失败: {}", path.display(), e)))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| Aria2Error::FileError(format!("读取 {} 失败: {}", path.display(), e)))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 规范化路径以便比较，文件不存在时保持原样
+fn normalize_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// ============================================================================
+// 端口管理
+// ============================================================================
+
+/// 检查端口是否可用
+#[cfg(feature = "daemon")]
+pub fn check_port_available(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// 查找可用端口
+#[cfg(feature = "daemon")]
+pub fn find_available_port() -> Aria2Result<u16> {
+    for port in DEFAULT_PORT..=(DEFAULT_PORT + MAX_PORT_RANGE) {
+        if check_port_available(port) {
+            return Ok(port);
+        }
+    }
+    Err(Aria2Error::PortError("未找到可用端口".to_string()))
+}
+
+/// 终止所有aria2c.exe进程
+#[cfg(feature = "daemon")]
+pub fn kill_existing_aria2() {
+    let _ = Command::new("taskkill").args(["/F", "/IM", "aria2c.exe"]).output();
+}
+
+/// 启动 aria2 RPC 服务
+#[cfg(feature = "daemon")]
+pub async fn start_aria2_rpc(config: &Aria2Config) -> Aria2Result<Aria2Instance> {
+    config.validate()?;
+
+    // 先终止现有的aria2c.exe进程
+    if config.kill_existing {
+        kill_existing_aria2();
+    }
+
+    let port = config.port_policy.select(config.port)?;
+    if port != config.port {
+        tracing::info!(preferred = config.port, port, "首选端口被占用，改用其他端口");
+    }
+
+    let mut cmd = Command::new(&config.aria2_path);
+    cmd.args([
+        "--enable-rpc",
+        &format!("--rpc-listen-all={}", config.rpc_listen_all),
+        &format!("--rpc-listen-port={}", port),
+        &format!("--dir={}", config.download_dir.display()),
+        &format!("--max-connection-per-server={}", config.max_connections),
+        &format!("--split={}", config.max_connections),
+        &format!("--min-split-size={}", config.split_size),
+        "--continue=true",
+        &format!("--max-tries={}", config.max_tries),
+        &format!("--retry-wait={}", config.retry_wait),
+        &format!("--connect-timeout={}", config.connect_timeout),
+    ]);
+
+    if let Some(secret) = &config.secret {
+        cmd.arg(format!("--rpc-secret={}", secret));
+    } else if config.rpc_listen_all {
+        tracing::warn!("RPC 在所有网卡上监听但没有设置密钥，局域网内的设备都能控制 aria2");
+    }
+
+    if config.rpc_allow_origin_all {
+        cmd.arg("--rpc-allow-origin-all=true");
+    }
+
+    if let Some(size) = &config.rpc_max_request_size {
+        cmd.arg(format!("--rpc-max-request-size={}", size));
+    }
+
+    if config.http_accept_gzip {
+        cmd.arg("--http-accept-gzip=true");
+    }
+
+    if !config.check_certificate {
+        cmd.arg("--check-certificate=false");
+    }
+
+    if let Some(ca) = &config.ca_certificate {
+        cmd.arg(format!("--ca-certificate={}", ca.display()));
+    }
+
+    if let Some(netrc) = &config.netrc_path {
+        cmd.arg(format!("--netrc-path={}", netrc.display()));
+    }
+
+    if config.no_netrc {
+        cmd.arg("--no-netrc=true");
+    }
+
+    if let Some(cookies) = &config.cookies_file {
+        cmd.arg(format!("--load-cookies={}", cookies.display()));
+    }
+
+    if let Some(user_agent) = &config.user_agent {
+        cmd.arg(format!("--user-agent={}", user_agent));
+    }
+
+    if let Some(referer) = &config.referer {
+        cmd.arg(format!("--referer={}", referer));
+    }
+
+    if config.bt_require_crypto {
+        cmd.arg("--bt-require-crypto=true");
+    }
+
+    if let Some(level) = &config.bt_min_crypto_level {
+        cmd.arg(format!("--bt-min-crypto-level={}", level));
+    }
+
+    if let Some(max_peers) = config.bt_max_peers {
+        cmd.arg(format!("--bt-max-peers={}", max_peers));
+    }
+
+    if !config.bt_trackers.is_empty() {
+        cmd.arg(format!("--bt-tracker={}", config.bt_trackers.join(",")));
+    }
+
+    if let Some(hook) = &config.on_download_complete {
+        cmd.arg(format!("--on-download-complete={}", hook.display()));
+    }
+
+    if let Some(hook) = &config.on_download_error {
+        cmd.arg(format!("--on-download-error={}", hook.display()));
+    }
+
+    cmd.args(&config.extra_args);
+
+    let child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Aria2Error::ProcessError(e.to_string()))?;
+
+    let mut instance = Aria2Instance {
+        process: child,
+        port,
+        config: config.clone(),
+    };
+
+    // 等待 RPC 服务启动，同时确认密钥可用
+    if let Err(e) = wait_for_rpc_ready(port, &config.secret).await {
+        let _ = instance.kill();
+        return Err(e);
+    }
+
+    // 拉取最新的 tracker 列表，失败时继续使用配置中的列表
+    if let Some(list_url) = &config.bt_tracker_list_url {
+        match fetch_tracker_list(list_url).await {
+            Ok(fetched) => {
+                let mut trackers = config.bt_trackers.clone();
+                trackers.extend(fetched.into_iter().filter(|t| !config.bt_trackers.contains(t)));
+                let client = Aria2RpcClient::new(port, config.secret.clone());
+                if let Err(e) = client.update_trackers(&trackers).await {
+                    tracing::warn!(error = %e, "更新 tracker 列表失败");
+                }
+            }
+            Err(e) => tracing::warn!(url = %list_url, error = %e, "拉取 tracker 列表失败"),
+        }
+    }
+
+    Ok(instance)
+}
+
+/// 拉取 tracker 列表，支持按行或逗号分隔的格式
+pub async fn fetch_tracker_list(url: &str) -> Aria2Result<Vec<String>> {
+    let client = try_http_client()?;
+
+    let response = client.get(url).send().await
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(Aria2Error::DownloadError(format!("HTTP错误: {}", response.status())));
+    }
+
+    let body = response.text().await
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+
+    let mut trackers: Vec<String> = Vec::new();
+    for tracker in body.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| t.contains("://")) {
+        if !trackers.iter().any(|t| t == tracker) {
+            trackers.push(tracker.to_string());
+        }
+    }
+    Ok(trackers)
+}
+
+#[cfg(feature = "daemon")]
+async fn wait_for_rpc_ready(port: u16, secret: &Option<String>) -> Aria2Result<()> {
+    let client = Aria2RpcClient::new(port, secret.clone());
+
+    for _ in 0..30 {
+        match client.get_version().await {
+            Ok(_) => return Ok(()),
+            // 服务已经启动但拒绝了密钥，继续等待没有意义
+            Err(Aria2Error::Unauthorized(_)) => {
+                return Err(Aria2Error::Unauthorized(format!(
+                    "端口 {} 上的 aria2 拒绝了 RPC 密钥，请检查 secret 配置或是否有其他 aria2 占用该端口",
+                    port,
+                )));
+            }
+            Err(_) => {}
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    Err(Aria2Error::RpcError("RPC 服务启动超时".to_string()))
+}
+
+// ============================================================================
+// 任务标识
+// ============================================================================
+
+/// 检查任务的文件是否具有相同的URI和存储路径
+///
+/// 未指定目录时认为是相同的（使用默认目录）。
+fn is_same_task(files: &[FileInfo], uris: &[String], target_dir: Option<&str>) -> bool {
+    files.iter().any(|file| {
+        file.uris.iter().any(|u| uris.contains(&u.uri))
+            && target_dir.is_none_or(|dir| file.path.starts_with(dir))
+    })
+}
+
+/// 根据 URL 和存储路径生成确定性的任务 GID
+///
+/// 取 (url, dir, out) 的 UUIDv5 前 16 位十六进制字符，满足 aria2 对 GID 的格式要求。
+/// 同一个逻辑下载在进程重启后仍映射到同一个 GID，去重只需查询该 GID 即可。
+/// 失败或被移除的任务仍占用该 GID，[`Aria2RpcClient::add_uri`] 会先清除其结果再重新添加。
+pub fn task_gid(url: &str, options: Option<&DownloadOptions>) -> String {
+    let dir = options.and_then(|o| o.dir.as_deref()).unwrap_or("");
+    let out = options.and_then(|o| o.out.as_deref()).unwrap_or("");
+    let name = format!("{}\n{}\n{}", url, dir, out);
+
+    let uuid = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, name.as_bytes());
+    uuid.simple().to_string()[..16].to_string()
+}
+
+/// 解析纯文本链接列表，每行一个 URL，可用 `-> 文件名` 指定保存的文件名
+///
+/// 空行和以 `#` 开头的行会被忽略，例如：
+///
+/// ```text
+/// https://example.com/a.bin
+/// https://example.com/b?id=1 -> b.bin
+/// ```
+pub fn parse_url_list(text: &str) -> Vec<(String, Option<String>)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once("->") {
+            Some((url, name)) => {
+                let name = name.trim();
+                (url.trim().to_string(), (!name.is_empty()).then(|| name.to_string()))
+            }
+            None => (line.to_string(), None),
+        })
+        .collect()
+}
+
+// ============================================================================
+// RPC 客户端
+// ============================================================================
+
+pub struct Aria2RpcClient {
+    client: Client,
+    // 守护进程创建的客户端共用该地址，aria2 换端口重启后由守护进程更新
+    base_url: Arc<std::sync::RwLock<String>>,
+    // 与地址一样由守护进程共享，轮换密钥后所有客户端同时生效
+    secret: Arc<std::sync::RwLock<Option<String>>>,
+    request_id: Arc<AtomicU64>,
+    latency: Arc<LatencyRecorder>,
+    slow_call_threshold: Option<Duration>,
+    dump: Option<Arc<RpcDump>>,
+    // 串行化同一任务的“查重并添加”，同一守护进程创建的客户端共享
+    add_locks: Arc<KeyedLock>,
+    rate_limiter: Option<Arc<RpcRateLimiter>>,
+}
+
+/// RPC 请求的限流设置，0 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RpcRateLimit {
+    /// 每秒最多发出的请求数
+    pub max_per_second: u32,
+    /// 同时进行的请求数上限
+    pub max_concurrent: u32,
+}
+
+/// 客户端侧的 RPC 限流器，避免频繁轮询的界面压垮 aria2 的 RPC 线程导致超时
+///
+/// 多个客户端共用同一个限流器时共享额度，同一守护进程创建的客户端总是共用一个。
+#[derive(Debug)]
+pub struct RpcRateLimiter {
+    interval: Option<Duration>,
+    // 下一个请求最早可以发出的时间
+    next: Mutex<tokio::time::Instant>,
+    permits: Option<Arc<tokio::sync::Semaphore>>,
+}
+
+impl RpcRateLimiter {
+    pub fn new(limit: RpcRateLimit) -> Self {
+        Self {
+            interval: (limit.max_per_second > 0).then(|| Duration::from_secs(1) / limit.max_per_second),
+            next: Mutex::new(tokio::time::Instant::now()),
+            permits: (limit.max_concurrent > 0)
+                .then(|| Arc::new(tokio::sync::Semaphore::new(limit.max_concurrent as usize))),
+        }
+    }
+
+    /// 等待发送额度，返回的许可在请求完成前不能释放
+    async fn acquire(&self) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        let permit = match &self.permits {
+            Some(permits) => Arc::clone(permits).acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(interval) = self.interval {
+            let slot = {
+                let mut next = self.next.lock().unwrap();
+                let slot = (*next).max(tokio::time::Instant::now());
+                *next = slot + interval;
+                slot
+            };
+            tokio::time::sleep_until(slot).await;
+        }
+        permit
+    }
+}
+
+/// 按键加锁，不同键之间互不阻塞；没有持有者的键会被自动清理
+#[derive(Debug, Default)]
+pub(crate) struct KeyedLock {
+    locks: Mutex<HashMap<String, std::sync::Weak<tokio::sync::Mutex<()>>>>,
+}
+
+impl KeyedLock {
+    async fn lock(&self, key: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            match locks.get(key).and_then(std::sync::Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    locks.retain(|_, lock| lock.strong_count() > 0);
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(key.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+}
+
+// 每个方法保留的最近耗时样本数
+const LATENCY_WINDOW: usize = 1000;
+// 默认的慢调用阈值
+const DEFAULT_SLOW_RPC_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// 某个 RPC 方法最近调用的耗时统计（毫秒）
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcLatency {
+    pub method: String,
+    /// 累计调用次数
+    pub count: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+/// 按方法记录最近的 RPC 耗时，同一守护进程创建的客户端共享一份
+#[derive(Debug, Default)]
+pub(crate) struct LatencyRecorder {
+    samples: Mutex<HashMap<String, (u64, std::collections::VecDeque<u64>)>>,
+}
+
+impl LatencyRecorder {
+    fn record(&self, method: &str, elapsed_ms: u64) {
+        let mut samples = self.samples.lock().unwrap();
+        let (count, window) = samples.entry(method.to_string()).or_default();
+        *count += 1;
+        if window.len() == LATENCY_WINDOW {
+            window.pop_front();
+        }
+        window.push_back(elapsed_ms);
+    }
+
+    fn stats(&self) -> Vec<RpcLatency> {
+        let samples = self.samples.lock().unwrap();
+        let mut stats: Vec<RpcLatency> = samples.iter()
+            .map(|(method, (count, window))| {
+                let mut sorted: Vec<u64> = window.iter().copied().collect();
+                sorted.sort_unstable();
+                let percentile = |p: f64| {
+                    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+                    sorted[index]
+                };
+                RpcLatency {
+                    method: method.clone(),
+                    count: *count,
+                    p50_ms: percentile(0.50),
+                    p95_ms: percentile(0.95),
+                    p99_ms: percentile(0.99),
+                    max_ms: sorted[sorted.len() - 1],
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.method.cmp(&b.method));
+        stats
+    }
+}
+
+/// 一次原始的 JSON-RPC 请求及其响应，密钥已替换为 `token:***`
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcExchange {
+    /// 请求 ID，与 aria2 日志中的 id 对应
+    pub id: u64,
+    pub method: String,
+    pub request: Value,
+    /// aria2 返回的完整响应，连接失败或响应无法解析时为 None
+    pub response: Option<Value>,
+    /// 连接或解析失败的原因
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// 保存最近若干次原始 RPC 交互的环形缓冲区，用于向 aria2 报告问题时附上完整的请求和响应
+#[derive(Debug)]
+pub struct RpcDump {
+    capacity: usize,
+    exchanges: Mutex<std::collections::VecDeque<RpcExchange>>,
+}
+
+impl RpcDump {
+    /// 创建最多保留 `capacity` 条记录的缓冲区
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, exchanges: Mutex::new(std::collections::VecDeque::with_capacity(capacity)) }
+    }
+
+    fn record(&self, exchange: RpcExchange) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut exchanges = self.exchanges.lock().unwrap();
+        if exchanges.len() == self.capacity {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    /// 按时间顺序返回缓冲区中的记录
+    pub fn exchanges(&self) -> Vec<RpcExchange> {
+        self.exchanges.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.exchanges.lock().unwrap().clear();
+    }
+}
+
+/// 把请求中的 `token:<secret>`（包括 multicall 内层调用中的）替换为 `token:***`
+fn redact_secret(value: &Value) -> Value {
+    match value {
+        Value::String(s) if s.starts_with("token:") => Value::String("token:***".to_string()),
+        Value::Array(items) => Value::Array(items.iter().map(redact_secret).collect()),
+        Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), redact_secret(v))).collect()),
+        other => other.clone(),
+    }
+}
+
+/// 构造带上下文的 RPC 错误
+fn rpc_failure(method: &str, params: &[Value], code: Option<i64>, message: String) -> Aria2Error {
+    Aria2Error::RpcFailed(Box::new(RpcFailure::new(method, params, code, message)))
+}
+
+/// 把 aria2 返回的错误对象（{code, message} 或 multicall 的 {faultCode, faultString}）转换为 RPC 错误
+fn rpc_fault(method: &str, params: &[Value], error: &Value) -> Aria2Error {
+    let code = error.get("code").or_else(|| error.get("faultCode")).and_then(Value::as_i64);
+    // aria2 的错误码 1 也用于其他错误，只有消息为 "Unauthorized" 时才是密钥问题
+    let text = error.get("message").or_else(|| error.get("faultString")).and_then(Value::as_str);
+    if code == Some(1) && text == Some("Unauthorized") {
+        return Aria2Error::Unauthorized(format!("{} 被拒绝，RPC 密钥错误或缺失", method));
+    }
+    let message = error.get("message").or_else(|| error.get("faultString"))
+        .and_then(Value::as_str)
+        .map(|m| format!("服务器错误: {}", m))
+        .unwrap_or_else(|| format!("服务器错误: {}", error));
+    rpc_failure(method, params, code, message)
+}
+
+fn rpc_url(port: u16) -> String {
+    format!("http://localhost:{}/jsonrpc", port)
+}
+
+impl Aria2RpcClient {
+    pub fn new(port: u16, secret: Option<String>) -> Self {
+        Self {
+            client: http_client(),
+            base_url: Arc::new(std::sync::RwLock::new(rpc_url(port))),
+            secret: Arc::new(std::sync::RwLock::new(secret)),
+            request_id: Arc::new(AtomicU64::new(1)),
+            latency: Arc::new(LatencyRecorder::default()),
+            slow_call_threshold: Some(DEFAULT_SLOW_RPC_THRESHOLD),
+            dump: None,
+            add_locks: Arc::new(KeyedLock::default()),
+            rate_limiter: None,
+        }
+    }
+
+    /// 使用指定的 HTTP 客户端，而不是共享客户端
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// 与守护进程共用 RPC 地址和密钥，端口或密钥变化后无需重新创建客户端
+    #[cfg(feature = "daemon")]
+    pub(crate) fn with_shared_endpoint(
+        mut self,
+        url: Arc<std::sync::RwLock<String>>,
+        secret: Arc<std::sync::RwLock<Option<String>>>,
+    ) -> Self {
+        self.base_url = url;
+        self.secret = secret;
+        self
+    }
+
+    /// 当前的 RPC 地址
+    pub fn base_url(&self) -> String {
+        self.base_url.read().unwrap().clone()
+    }
+
+    /// 切换到新端口，与该客户端共用地址的其他客户端同时生效
+    pub fn set_port(&self, port: u16) {
+        *self.base_url.write().unwrap() = rpc_url(port);
+    }
+
+    /// 设置慢调用阈值，耗时超过该值的调用会记录警告日志；None 表示不警告
+    pub fn with_slow_call_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_call_threshold = threshold;
+        self
+    }
+
+    /// 把原始请求和响应记录到缓冲区，多个客户端可以共用同一个缓冲区
+    ///
+    /// 无论是否设置，原始报文都会以 TRACE 级别写入日志（密钥已隐去）。
+    pub fn with_dump(mut self, dump: Arc<RpcDump>) -> Self {
+        self.dump = Some(dump);
+        self
+    }
+
+    /// 按限流器控制请求速率和并发数，多个客户端可以共用同一个限流器
+    pub fn with_rate_limiter(mut self, limiter: Arc<RpcRateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// 与其他客户端共享添加任务时的查重锁
+    #[cfg(feature = "daemon")]
+    pub(crate) fn with_add_locks(mut self, locks: Arc<KeyedLock>) -> Self {
+        self.add_locks = locks;
+        self
+    }
+
+    /// 与其他客户端共享耗时统计
+    #[cfg(feature = "daemon")]
+    pub(crate) fn with_latency_recorder(mut self, latency: Arc<LatencyRecorder>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// 各 RPC 方法最近 1000 次调用的耗时百分位数
+    pub fn latency_stats(&self) -> Vec<RpcLatency> {
+        self.latency.stats()
+    }
+
+    async fn call_method<T, R>(&self, method: &str, params: T) -> Aria2Result<R>
+    where
+        T: Serialize,
+        R: for<'de> Deserialize<'de>,
+    {
+        let rpc_params = self.build_params(params)?;
+        let result = self.send_request(method, &rpc_params).await?;
+        serde_json::from_value(result)
+            .map_err(|e| rpc_failure(method, &rpc_params, None, format!("解析响应失败: {}", e)))
+    }
+
+    /// 构造带 secret 的参数列表
+    fn build_params<T: Serialize>(&self, params: T) -> Aria2Result<Vec<Value>> {
+        let mut rpc_params = Vec::new();
+
+        // 添加 secret（如果配置了）
+        if let Some(secret) = self.secret.read().unwrap().as_ref() {
+            rpc_params.push(Value::String(format!("token:{}", secret)));
+        }
+
+        // 添加其他参数
+        let param_value = serde_json::to_value(&params)
+            .map_err(|e| Aria2Error::RpcError(e.to_string()))?;
+
+        // 如果参数是数组，则展开每个元素作为单独的参数
+        if let Value::Array(array) = param_value {
+            rpc_params.extend(array);
+        } else if !param_value.is_null() {
+            rpc_params.push(param_value);
+        }
+
+        Ok(rpc_params)
+    }
+
+    /// 发送 JSON-RPC 请求并返回 result 字段
+    async fn send_request(&self, method: &str, rpc_params: &[Value]) -> Aria2Result<Value> {
+        let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request_id.to_string(),
+            "method": method,
+            "params": rpc_params
+        });
+
+        let _permit = match &self.rate_limiter {
+            Some(limiter) => limiter.acquire().await,
+            None => None,
+        };
+        let started = std::time::Instant::now();
+        let url = self.base_url();
+        let result = async {
+            let response = self.client
+                .post(&url)
+                .json(&request)
+                .send()
+                .await
+                .map_err(|e| rpc_failure(method, rpc_params, None, e.to_string()))?;
+
+            response.json::<Value>().await
+                .map_err(|e| rpc_failure(method, rpc_params, None, e.to_string()))
+        }.await;
+
+        let elapsed = started.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        self.latency.record(method, elapsed_ms);
+
+        if self.dump.is_some() || tracing::enabled!(tracing::Level::TRACE) {
+            let exchange = RpcExchange {
+                id: request_id,
+                method: method.to_string(),
+                request: redact_secret(&request),
+                response: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+                elapsed_ms,
+            };
+            let response = exchange.response.clone().unwrap_or_default();
+            tracing::trace!(id = request_id, request = %exchange.request, response = %response, "RPC 原始报文");
+            if let Some(dump) = &self.dump {
+                dump.record(exchange);
+            }
+        }
+
+        let result = result.and_then(|mut rpc_response| match rpc_response.get("error") {
+            Some(error) => Err(rpc_fault(method, rpc_params, error)),
+            None => Ok(rpc_response["result"].take()),
+        });
+        match &result {
+            Ok(_) => tracing::debug!(method, id = request_id, elapsed_ms, "RPC 调用完成"),
+            Err(e) => tracing::debug!(method, id = request_id, elapsed_ms, error = %e, "RPC 调用失败"),
+        }
+        if self.slow_call_threshold.is_some_and(|threshold| elapsed > threshold) {
+            tracing::warn!(method, id = request_id, elapsed_ms, "RPC 调用缓慢");
+        }
+        result
+    }
+
+    /// 通过 system.multicall 在一次请求中执行多个方法
+    ///
+    /// 每个调用的参数与普通调用相同（数组会展开为多个参数），返回结果与调用一一对应。
+    pub async fn multicall<T: Serialize>(&self, calls: Vec<(&str, T)>) -> Aria2Result<Vec<Aria2Result<Value>>> {
+        let mut methods = Vec::with_capacity(calls.len());
+        for (method, params) in calls {
+            methods.push(serde_json::json!({
+                "methodName": method,
+                "params": self.build_params(params)?,
+            }));
+        }
+
+        // system.multicall 本身不接受 token，token 放在每个调用的参数里
+        let params = [Value::Array(methods)];
+        let result = self.send_request("system.multicall", &params).await?;
+        let Value::Array(results) = result else {
+            return Err(Aria2Error::RpcError("system.multicall 返回格式错误".to_string()));
+        };
+
+        // 成功的结果被包在单元素数组中，失败的结果是 {faultCode, faultString}
+        Ok(results.into_iter()
+            .zip(params[0].as_array().into_iter().flatten())
+            .map(|(item, call)| match item {
+                Value::Array(mut values) if values.len() == 1 => Ok(values.remove(0)),
+                fault => {
+                    let call_params = call["params"].as_array().map(Vec::as_slice).unwrap_or_default();
+                    Err(rpc_fault(call["methodName"].as_str().unwrap_or_default(), call_params, &fault))
+                }
+            })
+            .collect())
+    }
+
+    /// 汇总所有活跃任务的进度，只需一次 multicall
+    pub async fn global_progress(&self) -> Aria2Result<GlobalProgress> {
+        let keys = ["totalLength", "completedLength"];
+        let mut results = self.multicall(vec![
+            ("aria2.tellActive", serde_json::json!([keys])),
+            ("aria2.getGlobalStat", Value::Null),
+        ]).await?.into_iter();
+
+        let active = results.next().unwrap_or_else(|| Err(Aria2Error::RpcError("缺少 tellActive 结果".to_string())))?;
+        let stat = results.next().unwrap_or_else(|| Err(Aria2Error::RpcError("缺少 getGlobalStat 结果".to_string())))?;
+
+        let number = |value: &Value, key: &str| -> u64 {
+            value[key].as_str().and_then(|s| s.parse().ok()).unwrap_or_default()
+        };
+
+        let mut progress = GlobalProgress {
+            download_speed: number(&stat, "downloadSpeed"),
+            upload_speed: number(&stat, "uploadSpeed"),
+            ..Default::default()
+        };
+        for task in active.as_array().into_iter().flatten() {
+            progress.total_bytes += number(task, "totalLength");
+            progress.downloaded_bytes += number(task, "completedLength");
+            progress.num_active += 1;
+        }
+        Ok(progress)
+    }
+
+    /// 添加 URI 下载任务
+    ///
+    /// 相同的任务（URL + 存储路径）正在下载、等待、暂停或已完成时直接返回其 GID；
+    /// 已失败或被移除时先清除其结果，再以同一个 GID 重新添加。
+    pub async fn add_uri(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        self.add_uri_cancellable(uris, options, &CancellationToken::new()).await
+    }
+
+    /// 添加 URI 下载任务，可通过 `token` 中止去重扫描
+    ///
+    /// 取消只在提交 aria2.addUri 之前生效，任务一旦提交就不会被撤回。
+    pub async fn add_uri_cancellable(&self, uris: Vec<String>, options: Option<DownloadOptions>, token: &CancellationToken) -> Aria2Result<String> {
+        let first_uri = uris.first()
+            .ok_or_else(|| Aria2Error::ConfigError("URI 列表为空".to_string()))?;
+
+        let mut opts = options.unwrap_or_default();
+        opts.validate()?;
+
+        // 同一任务（URL + 存储路径）的查重和添加必须原子进行，否则并发添加会都通过查重
+        let key = task_gid(first_uri, Some(&opts));
+        let _guard = tokio::select! {
+            _ = token.cancelled() => return Err(Aria2Error::Cancelled),
+            guard = self.add_locks.lock(&key) => guard,
+        };
+
+        // 检查是否存在相同URI和存储路径的任务
+        let existing = tokio::select! {
+            _ = token.cancelled() => return Err(Aria2Error::Cancelled),
+            existing = self.gid_for_task(&uris, Some(&opts)) => existing?,
+        };
+        if let Some(existing_gid) = existing {
+            // 失败或被移除的任务不应阻止重新下载，清除结果后释放其 GID
+            match self.tell_status(&existing_gid).await {
+                Ok(status) if matches!(status.status.as_str(), "error" | "removed") => {
+                    self.remove_download_result(&existing_gid).await?;
+                }
+                _ => return Ok(existing_gid),
+            }
+        }
+
+        if token.is_cancelled() {
+            return Err(Aria2Error::Cancelled);
+        }
+
+        opts.gid = Some(opts.gid.clone().unwrap_or(key));
+        match opts.position {
+            Some(position) => self.call_method("aria2.addUri", (uris, opts.resolve(), position)).await,
+            None => self.call_method("aria2.addUri", (uris, opts.resolve())).await,
+        }
+    }
+
+    /// 查找任务对应的 GID
+    ///
+    /// 先查询确定性 GID，再扫描其他工具（AriaNg、脚本等）添加的同 URI、同路径任务。
+    /// 返回的任务可能处于任何状态，包括已失败或被移除。
+    pub async fn gid_for_task(&self, uris: &[String], options: Option<&DownloadOptions>) -> Aria2Result<Option<String>> {
+        let Some(first_uri) = uris.first() else {
+            return Ok(None);
+        };
+
+        let gid = options
+            .and_then(|o| o.gid.clone())
+            .unwrap_or_else(|| task_gid(first_uri, options));
+        if self.tell_status(&gid).await.is_ok() {
+            return Ok(Some(gid));
+        }
+
+        self.find_existing_task(uris, &options.cloned()).await
+    }
+
+    /// 查找具有相同URI和存储路径的现有任务
+    ///
+    /// 通过一次 multicall 只取回 gid 和 files，在本地比较，避免每个历史任务都请求一次。
+    async fn find_existing_task(&self, uris: &[String], options: &Option<DownloadOptions>) -> Aria2Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct TaskFiles {
+            gid: String,
+            #[serde(default)]
+            files: Vec<FileInfo>,
+        }
+
+        let keys = ["gid", "files"];
+        let results = self.multicall(vec![
+            ("aria2.tellActive", serde_json::json!([keys])),
+            ("aria2.tellWaiting", serde_json::json!([0, 1000, keys])),
+            ("aria2.tellStopped", serde_json::json!([0, 1000, keys])),
+        ]).await?;
+
+        // 某个列表获取失败时跳过，与逐个查询时的行为一致
+        let target_dir = options.as_ref().and_then(|o| o.dir.as_deref());
+        for list in results.into_iter().flatten() {
+            let Ok(tasks) = serde_json::from_value::<Vec<TaskFiles>>(list) else {
+                continue;
+            };
+            if let Some(task) = tasks.into_iter().find(|t| is_same_task(&t.files, uris, target_dir)) {
+                return Ok(Some(task.gid));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// 获取下载状态
+    pub async fn tell_status(&self, gid: &str) -> Aria2Result<DownloadStatus> {
+        self.call_method("aria2.tellStatus", (gid, STATUS_KEYS)).await
+    }
+
+    /// 获取活跃下载列表
+    pub async fn tell_active(&self) -> Aria2Result<Vec<DownloadStatus>> {
+        self.call_method("aria2.tellActive", (STATUS_KEYS,)).await
+    }
+
+    /// 获取等待下载列表
+    pub async fn tell_waiting(&self, offset: u32, num: u32) -> Aria2Result<Vec<DownloadStatus>> {
+        self.call_method("aria2.tellWaiting", (offset, num, STATUS_KEYS)).await
+    }
+
+    /// 获取已停止下载列表
+    pub async fn tell_stopped(&self, offset: u32, num: u32) -> Aria2Result<Vec<DownloadStatus>> {
+        self.call_method("aria2.tellStopped", (offset, num, STATUS_KEYS)).await
+    }
+
+    /// 通过一次 multicall 获取活跃、等待和已停止的全部任务
+    pub async fn tell_all(&self) -> Aria2Result<Vec<DownloadStatus>> {
+        let results = self.multicall(vec![
+            ("aria2.tellActive", serde_json::json!([STATUS_KEYS])),
+            ("aria2.tellWaiting", serde_json::json!([0, 1000, STATUS_KEYS])),
+            ("aria2.tellStopped", serde_json::json!([0, 1000, STATUS_KEYS])),
+        ]).await?;
+
+        let mut tasks = Vec::new();
+        for result in results {
+            let list: Vec<DownloadStatus> = serde_json::from_value(result?)
+                .map_err(|e| Aria2Error::RpcError(format!("解析响应失败: {}", e)))?;
+            tasks.extend(list);
+        }
+        Ok(tasks)
+    }
+
+    /// 获取下载文件信息
+    pub async fn get_files(&self, gid: &str) -> Aria2Result<Vec<FileInfo>> {
+        self.call_method("aria2.getFiles", gid).await
+    }
+
+    /// 获取全局统计信息
+    pub async fn get_global_stat(&self) -> Aria2Result<GlobalStat> {
+        self.call_method("aria2.getGlobalStat", ()).await
+    }
+
+    /// 暂停下载
+    pub async fn pause(&self, gid: &str) -> Aria2Result<String> {
+        self.call_method("aria2.pause", gid).await
+    }
+
+    /// 恢复下载
+    pub async fn unpause(&self, gid: &str) -> Aria2Result<String> {
+        self.call_method("aria2.unpause", gid).await
+    }
+
+    /// 移除下载
+    pub async fn remove(&self, gid: &str) -> Aria2Result<String> {
+        self.call_method("aria2.remove", gid).await
+    }
+
+    /// 强制暂停下载（不等待与服务器的交互结束）
+    pub async fn force_pause(&self, gid: &str) -> Aria2Result<String> {
+        self.call_method("aria2.forcePause", gid).await
+    }
+
+    /// 强制移除下载
+    pub async fn force_remove(&self, gid: &str) -> Aria2Result<String> {
+        self.call_method("aria2.forceRemove", gid).await
+    }
+
+    /// 暂停所有下载
+    pub async fn pause_all(&self) -> Aria2Result<String> {
+        self.call_method("aria2.pauseAll", ()).await
+    }
+
+    /// 恢复所有下载
+    pub async fn unpause_all(&self) -> Aria2Result<String> {
+        self.call_method("aria2.unpauseAll", ()).await
+    }
+
+    /// 获取下载使用的 URI 列表
+    pub async fn get_uris(&self, gid: &str) -> Aria2Result<Vec<UriInfo>> {
+        self.call_method("aria2.getUris", gid).await
+    }
+
+    /// 获取下载的选项
+    pub async fn get_option(&self, gid: &str) -> Aria2Result<HashMap<String, String>> {
+        self.call_method("aria2.getOption", gid).await
+    }
+
+    /// 修改下载的选项
+    pub async fn change_option(&self, gid: &str, options: HashMap<String, String>) -> Aria2Result<String> {
+        self.call_method("aria2.changeOption", (gid, options)).await
+    }
+
+    /// 修改正在进行的 BitTorrent 下载所选择的文件，`indices` 从 1 开始
+    pub async fn update_file_selection(&self, gid: &str, indices: &[usize]) -> Aria2Result<String> {
+        if indices.is_empty() {
+            return Err(Aria2Error::ConfigError("至少需要选择一个文件".to_string()));
+        }
+        if indices.contains(&0) {
+            return Err(Aria2Error::ConfigError("文件序号从 1 开始".to_string()));
+        }
+
+        let select_file = indices.iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut options = HashMap::new();
+        options.insert("select-file".to_string(), select_file);
+        self.change_option(gid, options).await
+    }
+
+    /// 替换之后添加的磁力链接和种子使用的全局 tracker 列表
+    pub async fn update_trackers(&self, trackers: &[String]) -> Aria2Result<String> {
+        let mut options = HashMap::new();
+        options.insert("bt-tracker".to_string(), trackers.join(","));
+        self.change_global_option(options).await
+    }
+
+    /// 获取全局选项
+    pub async fn get_global_option(&self) -> Aria2Result<HashMap<String, String>> {
+        self.call_method("aria2.getGlobalOption", ()).await
+    }
+
+    /// 修改全局选项
+    pub async fn change_global_option(&self, options: HashMap<String, String>) -> Aria2Result<String> {
+        self.call_method("aria2.changeGlobalOption", options).await
+    }
+
+    /// 调整下载在等待队列中的位置，`how` 为 POS_SET、POS_CUR 或 POS_END
+    pub async fn change_position(&self, gid: &str, pos: i64, how: &str) -> Aria2Result<i64> {
+        self.call_method("aria2.changePosition", (gid, pos, how)).await
+    }
+
+    /// 从内存中移除已完成/出错/已移除的下载结果
+    pub async fn remove_download_result(&self, gid: &str) -> Aria2Result<String> {
+        self.call_method("aria2.removeDownloadResult", gid).await
+    }
+
+    /// 清除所有已完成/出错/已移除的下载结果
+    pub async fn purge_download_result(&self) -> Aria2Result<String> {
+        self.call_method("aria2.purgeDownloadResult", ()).await
+    }
+
+    /// 获取 aria2 版本及启用的功能
+    pub async fn get_version(&self) -> Aria2Result<VersionInfo> {
+        self.call_method("aria2.getVersion", ()).await
+    }
+
+    /// aria2 支持的全部 RPC 方法名，用于检查 PATH 上较旧的 aria2 是否支持某个方法
+    pub async fn list_methods(&self) -> Aria2Result<Vec<String>> {
+        // 这两个方法不接受 secret token
+        self.call_without_token("system.listMethods").await
+    }
+
+    /// aria2 支持的全部通知名，如 "aria2.onDownloadStart"
+    pub async fn list_notifications(&self) -> Aria2Result<Vec<String>> {
+        self.call_without_token("system.listNotifications").await
+    }
+
+    /// 检查 aria2 是否支持指定的 RPC 方法
+    pub async fn supports_method(&self, method: &str) -> Aria2Result<bool> {
+        Ok(self.list_methods().await?.iter().any(|m| m == method))
+    }
+
+    async fn call_without_token<R: for<'de> Deserialize<'de>>(&self, method: &str) -> Aria2Result<R> {
+        let result = self.send_request(method, &[]).await?;
+        serde_json::from_value(result)
+            .map_err(|e| rpc_failure(method, &[], None, format!("解析响应失败: {}", e)))
+    }
+
+    /// 将已完成的下载解压到目标目录
+    #[cfg(feature = "extract")]
+    pub async fn extract_download<F>(&self, gid: &str, target_dir: &Path, progress: F) -> Aria2Result<Vec<PathBuf>>
+    where
+        F: FnMut(u64, u64) + Send + 'static,
+    {
+        let archive = self.final_path(gid).await?;
+
+        let target_dir = target_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || extract_archive(&archive, &target_dir, progress))
+            .await
+            .map_err(|e| Aria2Error::ExtractError(e.to_string()))?
+    }
+
+    /// 获取已完成下载的最终文件路径
+    ///
+    /// aria2 可能因自动重命名或响应头而改变文件名，这里读取的是实际写入的路径。
+    /// 多文件任务返回第一个文件的路径。
+    pub async fn final_path(&self, gid: &str) -> Aria2Result<PathBuf> {
+        let status = self.tell_status(gid).await?;
+        if status.status != "complete" {
+            return Err(Aria2Error::FileError(format!("任务尚未完成: {}", gid)));
+        }
+
+        self.get_files(gid).await?
+            .into_iter()
+            .map(|f| PathBuf::from(f.path))
+            .find(|p| !p.as_os_str().is_empty())
+            .ok_or_else(|| Aria2Error::FileError(format!("任务没有文件: {}", gid)))
+    }
+
+    /// 将已完成的下载移动到新位置，返回文件的最终路径
+    ///
+    /// aria2 不支持修改已完成任务的路径，移动后其下载结果中仍记录原路径。
+    pub async fn relocate(&self, gid: &str, new_path: &Path) -> Aria2Result<PathBuf> {
+        let current = self.final_path(gid).await?;
+
+        let new_path = new_path.to_path_buf();
+        tokio::task::spawn_blocking(move || move_file(&current, &new_path))
+            .await
+            .map_err(|e| Aria2Error::FileError(e.to_string()))?
+    }
+
+    /// 查找目录中没有对应 aria2 任务的 .aria2 控制文件
+    pub async fn find_orphaned_control_files(&self, dir: &Path) -> Aria2Result<Vec<PathBuf>> {
+        let mut control_files = Vec::new();
+        collect_control_files(dir, &mut control_files)?;
+        if control_files.is_empty() {
+            return Ok(control_files);
+        }
+
+        // 一次 multicall 收集 aria2 中所有任务（活跃、等待、已停止）的文件路径，
+        // 按 getGlobalStat 报告的数量分页，任务再多也不会漏掉
+        #[derive(Deserialize)]
+        struct TaskFiles {
+            #[serde(default)]
+            dir: String,
+            #[serde(default)]
+            files: Vec<FileInfo>,
+            bittorrent: Option<serde_json::Value>,
+        }
+
+        const PAGE: u32 = 1000;
+        let stat = self.get_global_stat().await?;
+        let count = |value: &str| value.parse::<u32>().unwrap_or(0);
+        let keys = ["dir", "files", "bittorrent"];
+        let mut calls = vec![("aria2.tellActive", serde_json::json!([keys]))];
+        // 多取一页，覆盖查询数量之后新加入的任务
+        for page in 0..=count(&stat.num_waiting) / PAGE {
+            calls.push(("aria2.tellWaiting", serde_json::json!([page * PAGE, PAGE, keys])));
+        }
+        for page in 0..=count(&stat.num_stopped) / PAGE {
+            calls.push(("aria2.tellStopped", serde_json::json!([page * PAGE, PAGE, keys])));
+        }
+        let results = self.multicall(calls).await?;
+
+        let mut known = std::collections::HashSet::new();
+        for result in results {
+            let tasks: Vec<TaskFiles> = serde_json::from_value(result?)
+                .map_err(|e| Aria2Error::RpcError(format!("解析响应失败: {}", e)))?;
+            for task in tasks {
+                known.extend(task.files.iter()
+                    .filter(|f| !f.path.is_empty())
+                    .map(|f| normalize_path(Path::new(&f.path))));
+                // 多文件种子的控制文件是 <dir>/<种子名>.aria2，不对应任何单个文件
+                let name = task.bittorrent.as_ref()
+                    .and_then(|bt| bt.pointer("/info/name"))
+                    .and_then(|name| name.as_str());
+                if let Some(name) = name.filter(|_| !task.dir.is_empty()) {
+                    known.insert(normalize_path(&Path::new(&task.dir).join(name)));
+                }
+            }
+        }
+
+        Ok(control_files.into_iter()
+            .filter(|control| !known.contains(&normalize_path(&control.with_extension(""))))
+            .collect())
+    }
+
+    /// 清理孤立的 .aria2 控制文件，`dry_run` 为 true 时只报告不删除
+    pub async fn cleanup_orphaned_control_files(&self, dir: &Path, dry_run: bool) -> Aria2Result<Vec<PathBuf>> {
+        let orphaned = self.find_orphaned_control_files(dir).await?;
+
+        if !dry_run {
+            for path in &orphaned {
+                std::fs::remove_file(path)
+                    .map_err(|e| Aria2Error::FileError(format!("删除 {} 失败: {}", path.display(), e)))?;
+            }
+        }
+
+        Ok(orphaned)
+    }
+
+    /// 关闭 aria2
+    pub async fn shutdown(&self) -> Aria2Result<String> {
+        self.call_method("aria2.shutdown", ()).await
+    }
+
+    /// 把当前会话保存到 `--save-session` 指定的文件，未设置该选项时返回错误
+    pub async fn save_session(&self) -> Aria2Result<String> {
+        self.call_method("aria2.saveSession", ()).await
+    }
+}
+
+// ============================================================================
+// 任务持久化
+// ============================================================================
+
+/// 由管理器添加的任务记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub gid: String,
+    pub uris: Vec<String>,
+    pub options: DownloadOptions,
+    /// 调用方附加的任意元数据
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// 任务已下载完成，恢复时不再重新添加
+    #[serde(default)]
+    pub completed: bool,
+}
+
+/// 任务快照，用于导出/导入任务列表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskSnapshot {
+    #[serde(flatten)]
+    pub record: TaskRecord,
+    /// 导出时 aria2 中的状态，aria2 中已没有该任务时为 None
+    pub status: Option<DownloadStatus>,
+}
+
+/// 任务记录的存储后端
+///
+/// 默认提供基于 JSON 文件的 [`JsonTaskStore`]，也可以接入 burncloud-database 等其他实现，
+/// 让任务状态在 BurnCloud 各组件间以同样的方式在重启后保留。
+#[async_trait::async_trait]
+pub trait TaskStore: Send + Sync {
+    /// 加载全部任务记录
+    async fn load(&self) -> Aria2Result<Vec<TaskRecord>>;
+
+    /// 保存任务记录，GID 相同的记录会被覆盖
+    async fn save(&self, record: &TaskRecord) -> Aria2Result<()>;
+
+    /// 删除任务记录
+    async fn remove(&self, gid: &str) -> Aria2Result<()>;
+}
+
+/// 把任务记录保存在单个 JSON 文件中的存储后端
+pub struct JsonTaskStore {
+    path: PathBuf,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl JsonTaskStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// 默认存储位置：BurnCloud 状态目录下的 tasks.json
+    pub fn default_path() -> PathBuf {
+        get_burncloud_state_dir().join("tasks.json")
+    }
+
+    fn read(&self) -> Aria2Result<Vec<TaskRecord>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Aria2Error::FileError(format!("解析 {} 失败: {}", self.path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(Aria2Error::FileError(format!("读取 {} 失败: {}", self.path.display(), e))),
+        }
+    }
+
+    fn write(&self, records: &[TaskRecord]) -> Aria2Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| Aria2Error::FileError(format!("创建目录失败: {}", e)))?;
+        }
+
+        let bytes = serde_json::to_vec_pretty(records)
+            .map_err(|e| Aria2Error::FileError(e.to_string()))?;
+
+        // 先写临时文件再重命名，避免崩溃时留下半个文件
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, bytes)
+            .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", tmp_path.display(), e)))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", self.path.display(), e)))
+    }
+}
+
+impl Default for JsonTaskStore {
+    fn default() -> Self {
+        Self::new(Self::default_path())
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskStore for JsonTaskStore {
+    async fn load(&self) -> Aria2Result<Vec<TaskRecord>> {
+        let _guard = self.lock.lock().await;
+        self.read()
+    }
+
+    async fn save(&self, record: &TaskRecord) -> Aria2Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut records = self.read()?;
+        match records.iter_mut().find(|r| r.gid == record.gid) {
+            Some(existing) => *existing = record.clone(),
+            None => records.push(record.clone()),
+        }
+        self.write(&records)
+    }
+
+    async fn remove(&self, gid: &str) -> Aria2Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut records = self.read()?;
+        let before = records.len();
+        records.retain(|r| r.gid != gid);
+        if records.len() != before {
+            self.write(&records)?;
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// 下载事件
+// ============================================================================
+
+/// 管理器观察到的下载事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DownloadEvent {
+    /// 任务通过管理器加入 aria2（包括重试和恢复）
+    Added { gid: String },
+    /// 任务开始下载，暂停后恢复时也会再次触发
+    Started { gid: String },
+    /// 任务被暂停
+    Paused { gid: String },
+    /// 任务下载完成
+    Completed { gid: String, path: String },
+    /// 任务下载失败
+    Failed { gid: String, path: String, error: Option<Aria2TaskError> },
+    /// 任务被移除
+    Removed { gid: String },
+    /// aria2 进程意外退出后被重新启动
+    DaemonRestarted { port: u16 },
+    /// 进入静默时段，全部下载已暂停
+    QuietHoursStarted,
+    /// 静默时段结束，`resumed` 为恢复的任务数
+    QuietHoursEnded { resumed: usize },
+}
+
+/// 下载事件流，由 [`Aria2Manager::events`] 创建
+///
+/// 开启 `stream` feature 后实现 `futures_core::Stream`，可以配合 `StreamExt::next` 使用。
+/// 处理过慢而错过的事件会被跳过。
+#[cfg(feature = "daemon")]
+pub struct EventStream {
+    inner: tokio_util::sync::ReusableBoxFuture<'static, EventRecv>,
+}
+
+#[cfg(feature = "daemon")]
+type EventRecv = (
+    Result<DownloadEvent, tokio::sync::broadcast::error::RecvError>,
+    tokio::sync::broadcast::Receiver<DownloadEvent>,
+);
+
+#[cfg(feature = "daemon")]
+async fn recv_event(mut rx: tokio::sync::broadcast::Receiver<DownloadEvent>) -> EventRecv {
+    let result = rx.recv().await;
+    (result, rx)
+}
+
+#[cfg(feature = "daemon")]
+impl EventStream {
+    fn new(rx: tokio::sync::broadcast::Receiver<DownloadEvent>) -> Self {
+        Self {
+            inner: tokio_util::sync::ReusableBoxFuture::new(recv_event(rx)),
+        }
+    }
+
+    /// 等待下一个事件，管理器被丢弃后返回 None
+    pub async fn recv(&mut self) -> Option<DownloadEvent> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    fn poll_recv(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<DownloadEvent>> {
+        use tokio::sync::broadcast::error::RecvError;
+
+        loop {
+            let (result, rx) = std::task::ready!(self.inner.poll(cx));
+            self.inner.set(recv_event(rx));
+            match result {
+                Ok(event) => return std::task::Poll::Ready(Some(event)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return std::task::Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl futures_core::Stream for EventStream {
+    type Item = DownloadEvent;
+
+    fn poll_next(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+        self.poll_recv(cx)
+    }
+}
+
+// ============================================================================
+// 下载历史
+// ============================================================================
+
+/// 一条下载历史记录，时间均为 Unix 时间戳（秒）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub gid: String,
+    /// 下载文件的路径，aria2 未报告时为空
+    pub path: String,
+    /// 最终状态：complete、error 或 removed
+    pub status: String,
+    /// 首次观察到任务开始下载的时间
+    pub started_at: Option<u64>,
+    pub completed_at: u64,
+    pub total_bytes: u64,
+    /// 平均速度（字节/秒），缺少开始时间时为 None
+    pub average_speed: Option<u64>,
+}
+
+/// 下载历史查询条件
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// 只返回该状态的记录
+    pub status: Option<String>,
+    /// 只返回该时间（Unix 时间戳）之后结束的记录
+    pub since: Option<u64>,
+    /// 最多返回的条数（从最近的开始）
+    pub limit: Option<usize>,
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// 追加一行 JSON 到 JSON Lines 文件
+#[cfg(feature = "daemon")]
+fn append_json_line<T: Serialize>(path: &Path, entry: &T) -> Aria2Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| Aria2Error::FileError(format!("创建目录失败: {}", e)))?;
+    }
+
+    let mut line = serde_json::to_string(entry)
+        .map_err(|e| Aria2Error::FileError(e.to_string()))?;
+    line.push('\n');
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", path.display(), e)))
+}
+
+#[cfg(feature = "daemon")]
+fn read_history(path: &Path) -> Aria2Result<Vec<HistoryEntry>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Aria2Error::FileError(format!("读取 {} 失败: {}", path.display(), e))),
+    };
+
+    // 跳过无法解析的行（例如写入中途崩溃留下的半行）
+    Ok(content.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+// ============================================================================
+// 传输统计
+// ============================================================================
+
+/// 下载和上传的字节数
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TransferTotals {
+    pub downloaded: u64,
+    pub uploaded: u64,
+}
+
+impl TransferTotals {
+    #[cfg(feature = "daemon")]
+    fn add(&mut self, downloaded: u64, uploaded: u64) {
+        self.downloaded += downloaded;
+        self.uploaded += uploaded;
+    }
+}
+
+/// 累计传输量，由 [`Aria2Manager::stats`] 获取
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransferStats {
+    /// 本次启动守护进程以来的传输量，不保存到磁盘
+    #[serde(skip)]
+    pub session: TransferTotals,
+    /// 全部传输量
+    pub overall: TransferTotals,
+    /// 按月（UTC）统计的传输量，键为 "2026-10" 形式
+    pub monthly: std::collections::BTreeMap<String, TransferTotals>,
+}
+
+impl TransferStats {
+    /// 从文件加载，文件不存在或无法解析时从零开始
+    #[cfg(feature = "daemon")]
+    fn load(path: &Path) -> Self {
+        std::fs::read(path).ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "daemon")]
+    fn save(&self, path: &Path) -> Aria2Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| Aria2Error::FileError(format!("创建目录失败: {}", e)))?;
+        }
+        let bytes = serde_json::to_vec_pretty(self)
+            .map_err(|e| Aria2Error::FileError(e.to_string()))?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, bytes)
+            .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", tmp_path.display(), e)))?;
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", path.display(), e)))
+    }
+
+    /// 本月（UTC）的传输量
+    pub fn this_month(&self) -> TransferTotals {
+        self.monthly.get(&month_key(unix_now())).copied().unwrap_or_default()
+    }
+
+    #[cfg(feature = "daemon")]
+    fn record(&mut self, downloaded: u64, uploaded: u64) {
+        self.session.add(downloaded, uploaded);
+        self.overall.add(downloaded, uploaded);
+        self.monthly.entry(month_key(unix_now())).or_default().add(downloaded, uploaded);
+    }
+}
+
+/// Unix 时间戳所在的年月（UTC），如 "2026-10"
+fn month_key(timestamp: u64) -> String {
+    // 按公历从 1970-01-01 起的天数推算年月
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}", year, month)
+}
+
+// ============================================================================
+// 简单守护进程
+// ============================================================================
+
+#[cfg(feature = "daemon")]
+#[derive(Clone)]
+pub struct Aria2Daemon {
+    instance: Arc<Mutex<Option<Aria2Instance>>>,
+    config: Aria2Config,
+    is_running: Arc<AtomicBool>,
+    restarts: Arc<AtomicU64>,
+    latency: Arc<LatencyRecorder>,
+    // 按 config.rpc_http 创建，该守护进程的所有 RPC 客户端共用连接池
+    rpc_http: Option<Client>,
+    // 所有 RPC 客户端共用的地址，aria2 换端口重启后更新
+    rpc_url: Arc<std::sync::RwLock<String>>,
+    // 所有 RPC 客户端共用的密钥，轮换密钥时更新，监控任务重启 aria2 时使用
+    rpc_secret: Arc<std::sync::RwLock<Option<String>>>,
+    rpc_dump: Arc<RpcDump>,
+    add_locks: Arc<KeyedLock>,
+    rate_limiter: Option<Arc<RpcRateLimiter>>,
+    // 通过 attach 连接的已有 aria2 的端口，此时不持有进程
+    attached_port: Option<u16>,
+}
+
+#[cfg(feature = "daemon")]
+impl Aria2Daemon {
+    pub fn new(config: Aria2Config) -> Self {
+        Self {
+            instance: Arc::new(Mutex::new(None)),
+            is_running: Arc::new(AtomicBool::new(false)),
+            restarts: Arc::new(AtomicU64::new(0)),
+            latency: Arc::new(LatencyRecorder::default()),
+            rpc_http: None,
+            rpc_url: Arc::new(std::sync::RwLock::new(String::new())),
+            rpc_secret: Arc::new(std::sync::RwLock::new(config.secret.clone())),
+            rpc_dump: Arc::new(RpcDump::new(config.rpc_dump_capacity)),
+            add_locks: Arc::new(KeyedLock::default()),
+            rate_limiter: config.rpc_rate_limit.map(|limit| Arc::new(RpcRateLimiter::new(limit))),
+            attached_port: None,
+            config,
+        }
+    }
+
+    /// 连接到已在 `config.port` 上运行的 aria2，不启动、监控或结束其进程
+    pub async fn attach(config: Aria2Config) -> Aria2Result<Self> {
+        let mut daemon = Self::new(config);
+        daemon.rpc_http = Some(daemon.config.rpc_http.build_client()?);
+        *daemon.rpc_url.write().unwrap() = rpc_url(daemon.config.port);
+        daemon.attached_port = Some(daemon.config.port);
+
+        let client = daemon.get_rpc_client()
+            .ok_or_else(|| Aria2Error::DaemonError("守护进程未运行".to_string()))?;
+        client.get_version().await?;
+        daemon.is_running.store(true, Ordering::SeqCst);
+        tracing::info!(port = daemon.config.port, "已连接到运行中的 aria2");
+        Ok(daemon)
+    }
+
+    /// 是否通过 [`Aria2Daemon::attach`] 连接到其他进程启动的 aria2
+    pub fn is_attached(&self) -> bool {
+        self.attached_port.is_some()
+    }
+
+    pub async fn start(&mut self) -> Aria2Result<()> {
+        if self.is_running.load(Ordering::SeqCst) {
+            return Err(Aria2Error::DaemonError("守护进程已在运行".to_string()));
+        }
+
+        self.rpc_http = Some(self.config.rpc_http.build_client()?);
+        let instance = start_aria2_rpc(&self.config).await?;
+        tracing::info!(port = instance.port, "aria2 RPC 服务已启动");
+        *self.rpc_url.write().unwrap() = rpc_url(instance.port);
+
+        *self.instance.lock().unwrap() = Some(instance);
+        self.is_running.store(true, Ordering::SeqCst);
+        self.spawn_monitor();
+
+        Ok(())
+    }
+
+    // 启动监控任务，aria2 意外退出时重启，重启次数达到上限后放弃
+    fn spawn_monitor(&self) {
+        let instance = Arc::clone(&self.instance);
+        let is_running = Arc::clone(&self.is_running);
+        let restarts = Arc::clone(&self.restarts);
+        let url = Arc::clone(&self.rpc_url);
+        let secret = Arc::clone(&self.rpc_secret);
+        let mut config = self.config.clone();
+
+        tokio::spawn(async move {
+            let mut last_tick = std::time::SystemTime::now();
+            // 失败的重启也计入上限，否则 aria2 无法再启动时会一直重试
+            let mut attempts = 0u64;
+
+            while is_running.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+                // 休眠期间守护进程可能已被关闭，不能把正常退出当作崩溃重启
+                if !is_running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                // 挂钟时间远超休眠时间，说明系统刚从睡眠中恢复
+                let now = std::time::SystemTime::now();
+                let gap = now.duration_since(last_tick).unwrap_or_default();
+                last_tick = now;
+                if gap > SUSPEND_DETECT_GAP {
+                    tracing::info!(gap_secs = gap.as_secs(), "检测到系统从睡眠中恢复，检查 aria2 状态");
+                    let port = instance.lock().unwrap().as_ref().map(|inst| inst.port);
+                    if let Some(port) = port {
+                        let secret = secret.read().unwrap().clone();
+                        recover_after_resume(&instance, port, &secret).await;
+                    }
+                }
+
+                let need_restart = {
+                    let mut lock = instance.lock().unwrap();
+                    match lock.as_mut() {
+                        Some(inst) => !inst.is_running(), // 检查进程是否还在运行
+                        None => true,
+                    }
+                };
+
+                if need_restart && config.max_restarts.is_some_and(|max| attempts >= max as u64) {
+                    tracing::error!("aria2 重启次数已达上限，停止重启");
+                    is_running.store(false, Ordering::SeqCst);
+                    *instance.lock().unwrap() = None;
+                    break;
+                }
+
+                if need_restart {
+                    tracing::warn!("检测到 aria2 已退出，重启中");
+                    config.secret = secret.read().unwrap().clone();
+                    attempts += 1;
+                    match start_aria2_rpc(&config).await {
+                        Ok(new_instance) => {
+                            tracing::info!(port = new_instance.port, "aria2 重启成功");
+                            *url.write().unwrap() = rpc_url(new_instance.port);
+                            *instance.lock().unwrap() = Some(new_instance);
+                            restarts.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(e @ Aria2Error::Unauthorized(_)) => {
+                            // 密钥被拒绝时反复重启只会得到同样的结果
+                            tracing::error!(error = %e, "aria2 重启失败，停止重启");
+                            is_running.store(false, Ordering::SeqCst);
+                            *instance.lock().unwrap() = None;
+                            break;
+                        }
+                        Err(e) => tracing::error!(error = %e, attempts, "aria2 重启失败"),
+                    }
+                }
+            }
+        });
+    }
+
+    pub async fn stop(&mut self) {
+        self.is_running.store(false, Ordering::SeqCst);
+        if self.is_attached() {
+            tracing::info!("已断开与 aria2 的连接");
+            return;
+        }
+
+        if let Some(ref mut instance) = self.instance.lock().unwrap().as_mut() {
+            let _ = instance.kill();
+        }
+
+        *self.instance.lock().unwrap() = None;
+        tracing::info!("aria2 守护进程已停止");
+    }
+
+    /// 保存会话并通过 aria2.shutdown 让 aria2 正常退出，超过 `timeout` 仍未退出时强制结束
+    ///
+    /// 正常退出时 aria2 会写入控制文件和 `--save-session` 会话文件，未完成的任务之后可以续传。
+    pub async fn stop_gracefully(&mut self, timeout: Duration) {
+        // 先清除运行标志，监控任务不会在 aria2 退出后重启它
+        self.is_running.store(false, Ordering::SeqCst);
+
+        if let Some(client) = self.get_rpc_client().filter(|_| !self.is_attached()) {
+            if let Err(e) = client.save_session().await {
+                tracing::debug!(error = %e, "保存 aria2 会话失败");
+            }
+            match client.shutdown().await {
+                Ok(_) => {
+                    let deadline = std::time::Instant::now() + timeout;
+                    while std::time::Instant::now() < deadline {
+                        let running = self.instance.lock().unwrap().as_mut().is_some_and(|inst| inst.is_running());
+                        if !running {
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "请求 aria2 退出失败，强制结束"),
+            }
+        }
+
+        self.stop().await;
+    }
+
+    /// 通过该守护进程的客户端发起的 RPC 调用耗时统计
+    pub fn latency_stats(&self) -> Vec<RpcLatency> {
+        self.latency.stats()
+    }
+
+    /// 该守护进程的客户端最近的原始 RPC 交互，需设置 `rpc_dump_capacity`
+    pub fn rpc_dump(&self) -> Vec<RpcExchange> {
+        self.rpc_dump.exchanges()
+    }
+
+    /// aria2 意外退出后被自动重启的次数
+    pub fn restart_count(&self) -> u64 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    /// 更换 RPC 密钥：用新密钥重启 aria2，并同时更新该守护进程创建的所有客户端
+    ///
+    /// aria2 不支持在运行时修改 `--rpc-secret`，重启期间的 RPC 调用会失败。
+    pub async fn rotate_secret(&self, secret: Option<String>) -> Aria2Result<()> {
+        let before = self.replace_secret(secret)?;
+        wait_for_restart(&self.restarts, before).await
+    }
+
+    // 在持有实例锁时更新密钥并结束进程，监控任务随后用新密钥重启 aria2；返回此前的重启次数
+    fn replace_secret(&self, secret: Option<String>) -> Aria2Result<u64> {
+        if !self.is_running() {
+            return Err(Aria2Error::DaemonError("守护进程未运行".to_string()));
+        }
+        if self.is_attached() {
+            return Err(Aria2Error::DaemonError("不能更换其他进程启动的 aria2 的密钥".to_string()));
+        }
+        let mut instance = self.instance.lock().unwrap();
+        let before = self.restarts.load(Ordering::SeqCst);
+        *self.rpc_secret.write().unwrap() = secret;
+        if let Some(instance) = instance.as_mut() {
+            instance.kill()?;
+        }
+        tracing::info!("RPC 密钥已更换，等待 aria2 重启");
+        Ok(before)
+    }
+
+    pub fn get_rpc_client(&self) -> Option<Aria2RpcClient> {
+        self.port().map(|port| {
+            let client = Aria2RpcClient::new(port, self.config.secret.clone())
+                .with_shared_endpoint(Arc::clone(&self.rpc_url), Arc::clone(&self.rpc_secret))
+                .with_dump(Arc::clone(&self.rpc_dump))
+                .with_add_locks(Arc::clone(&self.add_locks))
+                .with_slow_call_threshold(self.config.slow_rpc_threshold)
+                .with_latency_recorder(Arc::clone(&self.latency));
+            let client = match &self.rate_limiter {
+                Some(limiter) => client.with_rate_limiter(Arc::clone(limiter)),
+                None => client,
+            };
+            match &self.rpc_http {
+                Some(http) => client.with_http_client(http.clone()),
+                None => client,
+            }
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running.load(Ordering::SeqCst)
+    }
+
+    /// 当前 aria2 实例的 RPC 端口，实例重启后可能变化
+    pub fn port(&self) -> Option<u16> {
+        let port = self.instance.lock().unwrap().as_ref().map(|instance| instance.port);
+        port.or(self.attached_port.filter(|_| self.is_running()))
+    }
+}
+
+/// 等待监控任务完成一次重启
+#[cfg(feature = "daemon")]
+async fn wait_for_restart(restarts: &AtomicU64, before: u64) -> Aria2Result<()> {
+    for _ in 0..60 {
+        if restarts.load(Ordering::SeqCst) > before {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    Err(Aria2Error::DaemonError("等待 aria2 重启超时".to_string()))
+}
+
+/// 系统唤醒后检查 aria2：RPC 无响应时结束进程交给监控任务重启，
+/// 否则重新启动活跃任务，因为睡眠前建立的 TCP 连接通常已经失效
+#[cfg(feature = "daemon")]
+async fn recover_after_resume(instance: &Mutex<Option<Aria2Instance>>, port: u16, secret: &Option<String>) {
+    let client = Aria2RpcClient::new(port, secret.clone());
+
+    // 拒绝密钥说明服务仍在响应，重启也无法解决
+    let healthy = tokio::time::timeout(Duration::from_secs(5), client.get_version()).await
+        .is_ok_and(|r| r.is_ok() || matches!(r, Err(Aria2Error::Unauthorized(_))));
+    if !healthy {
+        tracing::warn!("aria2 在唤醒后无响应，准备重启");
+        if let Some(inst) = instance.lock().unwrap().as_mut() {
+            let _ = inst.kill();
+        }
+        return;
+    }
+
+    let Ok(active) = client.tell_active().await else { return };
+    for task in active {
+        if client.force_pause(&task.gid).await.is_ok() {
+            // forcePause 是异步完成的，等任务进入 paused 状态后再恢复
+            for _ in 0..10 {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                if client.tell_status(&task.gid).await.is_ok_and(|s| s.status == "paused") {
+                    break;
+                }
+            }
+            let _ = client.unpause(&task.gid).await;
+        }
+    }
+}
+
+// ============================================================================
+// 统一管理器 - 主要入口点
+// ============================================================================
+
+/// aria2 管理器
+///
+/// 内部状态由 `Arc` 共享，克隆开销很小，可以直接在多个 tokio 任务间传递。
+#[cfg(feature = "daemon")]
+#[derive(Clone)]
+pub struct Aria2Manager {
+    inner: Arc<ManagerInner>,
+}
+
+/// 按 GID 索引的任务表，保持 aria2 返回的顺序（活跃、等待、已停止）
+#[cfg(feature = "daemon")]
+#[derive(Debug, Default)]
+struct TaskTable {
+    tasks: Vec<DownloadStatus>,
+    index: HashMap<String, usize>,
+}
+
+#[cfg(feature = "daemon")]
+impl TaskTable {
+    fn get(&self, gid: &str) -> Option<&DownloadStatus> {
+        self.index.get(gid).map(|&i| &self.tasks[i])
+    }
+
+    /// 用一轮轮询的结果替换整张表
+    fn refresh(&mut self, tasks: Vec<DownloadStatus>) {
+        self.index = tasks.iter().enumerate().map(|(i, t)| (t.gid.clone(), i)).collect();
+        self.tasks = tasks;
+    }
+
+    /// 更新单个任务，新任务追加到末尾
+    fn upsert(&mut self, status: DownloadStatus) {
+        match self.index.get(&status.gid) {
+            Some(&i) => self.tasks[i] = status,
+            None => {
+                self.index.insert(status.gid.clone(), self.tasks.len());
+                self.tasks.push(status);
+            }
+        }
+    }
+
+    fn remove(&mut self, gid: &str) {
+        if let Some(i) = self.index.remove(gid) {
+            self.tasks.remove(i);
+            for index in self.index.values_mut().filter(|index| **index > i) {
+                *index -= 1;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.tasks.clear();
+        self.index.clear();
+    }
+}
+
+#[cfg(feature = "daemon")]
+struct ManagerInner {
+    daemon: Mutex<Option<Aria2Daemon>>,
+    config: Mutex<Aria2Config>,
+    // 串行化守护进程的启动与关闭
+    lifecycle: tokio::sync::Mutex<()>,
+    // 守护进程关闭时停止后台监视任务
+    watcher: Mutex<Option<CancellationToken>>,
+    store: Option<Arc<dyn TaskStore>>,
+    events: tokio::sync::broadcast::Sender<DownloadEvent>,
+    // download_now 临时提高并发数的任务及提高前的 max-concurrent-downloads
+    boosted: Mutex<HashMap<String, u32>>,
+    // 全部任务状态，由后台监视任务刷新，添加和移除任务时立即更新
+    snapshot: Mutex<TaskTable>,
+    // 空闲时后台监视任务停止轮询，添加或恢复任务时由此唤醒
+    wake: Arc<tokio::sync::Notify>,
+    // aria2 启用的功能（getVersion 的 enabledFeatures），首次添加种子或 Metalink 时获取
+    features: Mutex<Option<Vec<String>>>,
+    // 守护进程因空闲被关闭，添加下载时自动重新启动
+    idle_stopped: AtomicBool,
+    // 累计传输量，由后台监视任务按快照之间的差值累加
+    stats: Mutex<TransferStats>,
+    // 当前使用的限速方案，守护进程重启后重新应用
+    speed_profile: Mutex<Option<SpeedProfile>>,
+    // 首次切换限速方案前 aria2 的同时下载任务数，方案未指定任务数时恢复为该值
+    base_concurrent: Mutex<Option<u32>>,
+    #[cfg(feature = "notify")]
+    notifications: AtomicBool,
+}
+
+#[cfg(feature = "daemon")]
+impl Aria2Manager {
+    pub fn new() -> Self {
+        Self::with_config(Aria2Config::default())
+    }
+
+    pub fn with_config(config: Aria2Config) -> Self {
+        Self::build(config, None)
+    }
+
+    /// 使用任务存储创建管理器，守护进程启动时会重新添加 aria2 中缺失的任务
+    pub fn with_store(config: Aria2Config, store: Arc<dyn TaskStore>) -> Self {
+        Self::build(config, Some(store))
+    }
+
+    fn build(config: Aria2Config, store: Option<Arc<dyn TaskStore>>) -> Self {
+        let stats = config.stats_file.as_deref().map(TransferStats::load).unwrap_or_default();
+        Self {
+            inner: Arc::new(ManagerInner {
+                daemon: Mutex::new(None),
+                config: Mutex::new(config),
+                lifecycle: tokio::sync::Mutex::new(()),
+                watcher: Mutex::new(None),
+                store,
+                events: tokio::sync::broadcast::channel(256).0,
+                boosted: Mutex::new(HashMap::new()),
+                snapshot: Mutex::new(TaskTable::default()),
+                wake: Arc::new(tokio::sync::Notify::new()),
+                features: Mutex::new(None),
+                idle_stopped: AtomicBool::new(false),
+                stats: Mutex::new(stats),
+                speed_profile: Mutex::new(None),
+                base_concurrent: Mutex::new(None),
+                #[cfg(feature = "notify")]
+                notifications: AtomicBool::new(true),
+            }),
+        }
+    }
+
+    /// 获取当前配置
+    pub fn config(&self) -> Aria2Config {
+        self.inner.config.lock().unwrap().clone()
+    }
+
+    /// 下载并设置 aria2
+    pub async fn download_and_setup(&self) -> Aria2Result<()> {
+        self.download_and_setup_cancellable(&CancellationToken::new()).await
+    }
+
+    /// 下载并设置 aria2，可通过 `token` 中止下载
+    pub async fn download_and_setup_cancellable(&self, token: &CancellationToken) -> Aria2Result<()> {
+        self.download_and_setup_with_progress(token, |_, _, _| {}).await
+    }
+
+    /// 下载并设置 aria2，通过 `progress(阶段, 已完成, 总量)` 报告进度，可通过 `token` 中止下载
+    pub async fn download_and_setup_with_progress<F>(&self, token: &CancellationToken, progress: F) -> Aria2Result<()>
+    where
+        F: FnMut(BootstrapPhase, u64, u64),
+    {
+        tracing::info!("正在下载 aria2");
+        let aria2_path = tokio::select! {
+            _ = token.cancelled() => return Err(Aria2Error::Cancelled),
+            path = download_aria2_with_progress(progress) => path?,
+        };
+        tracing::info!(path = %aria2_path.display(), "aria2 下载完成");
+
+        self.set_aria2_path(aria2_path);
+        Ok(())
+    }
+
+    pub(crate) fn set_aria2_path(&self, path: PathBuf) {
+        self.inner.config.lock().unwrap().aria2_path = path;
+    }
+
+    /// 启动守护进程
+    pub async fn start_daemon(&self) -> Aria2Result<()> {
+        let _lifecycle = self.inner.lifecycle.lock().await;
+        self.start_locked().await
+    }
+
+    // 启动守护进程，调用方需持有 lifecycle 锁
+    async fn start_locked(&self) -> Aria2Result<()> {
+        if self.inner.daemon.lock().unwrap().is_some() {
+            return Err(Aria2Error::DaemonError("守护进程已存在".to_string()));
+        }
+
+        let mut daemon = Aria2Daemon::new(self.config());
+        daemon.start().await?;
+        self.install_locked(daemon).await;
+        tracing::info!("aria2 守护进程启动成功");
+        Ok(())
+    }
+
+    /// 连接到已在运行的 aria2（如 `burncloud-aria2 daemon start` 启动的进程），而不是自己启动
+    ///
+    /// 快照、事件和添加下载与自己启动时相同；不会重启或在空闲时关闭该 aria2，`shutdown` 只断开连接。
+    pub async fn attach_daemon(&self) -> Aria2Result<()> {
+        let _lifecycle = self.inner.lifecycle.lock().await;
+        if self.inner.daemon.lock().unwrap().is_some() {
+            return Err(Aria2Error::DaemonError("守护进程已存在".to_string()));
+        }
+        let daemon = Aria2Daemon::attach(self.config()).await?;
+        self.install_locked(daemon).await;
+        Ok(())
+    }
+
+    // 接管守护进程并启动监视任务，调用方需持有 lifecycle 锁
+    async fn install_locked(&self, daemon: Aria2Daemon) {
+        *self.inner.daemon.lock().unwrap() = Some(daemon);
+        self.inner.stats.lock().unwrap().session = TransferTotals::default();
+
+        let token = CancellationToken::new();
+        *self.inner.watcher.lock().unwrap() = Some(token.clone());
+        if let Some(path) = self.config().event_log {
+            self.spawn_event_log(path, token.clone());
+        }
+        self.spawn_watcher(token);
+        self.prepare_daemon().await;
+    }
+
+    // 新启动的 aria2 可能是不同的程序，也没有原来的任务和全局选项：重新检测功能、恢复任务并应用限速方案
+    async fn prepare_daemon(&self) {
+        *self.inner.features.lock().unwrap() = None;
+        if let Err(e) = self.restore_tasks().await {
+            tracing::warn!(error = %e, "恢复任务失败");
+        }
+        self.reapply_profile().await;
+    }
+
+    /// 更换 RPC 密钥并用新密钥重启 aria2，已创建的 RPC 客户端自动使用新密钥
+    ///
+    /// 重启后从任务存储中重新添加未完成的任务，下载进度由 aria2 的控制文件保留。
+    pub async fn rotate_secret(&self, secret: Option<String>) -> Aria2Result<()> {
+        let _lifecycle = self.inner.lifecycle.lock().await;
+
+        let (restarts, before) = {
+            let daemon = self.inner.daemon.lock().unwrap();
+            let daemon = daemon.as_ref()
+                .ok_or_else(|| Aria2Error::DaemonError("守护进程未运行".to_string()))?;
+            (Arc::clone(&daemon.restarts), daemon.replace_secret(secret.clone())?)
+        };
+        self.inner.config.lock().unwrap().secret = secret;
+        wait_for_restart(&restarts, before).await?;
+
+        // 重启后的 aria2 中没有原来的任务，从任务存储中重新添加
+        if let Err(e) = self.restore_tasks().await {
+            tracing::warn!(error = %e, "恢复任务失败");
+        }
+        Ok(())
+    }
+
+    /// 获取 RPC 客户端
+    pub fn get_rpc_client(&self) -> Option<&Aria2RpcClient> {
+        // 由于借用检查器限制，这里简化实现
+        None
+    }
+
+    /// 当前守护进程的 RPC 调用耗时统计，守护进程未启动时返回空列表
+    pub fn rpc_latency(&self) -> Vec<RpcLatency> {
+        self.inner.daemon.lock().unwrap().as_ref().map(|d| d.latency_stats()).unwrap_or_default()
+    }
+
+    /// 最近的原始 RPC 交互，需在配置中设置 `rpc_dump_capacity`
+    pub fn rpc_dump(&self) -> Vec<RpcExchange> {
+        self.inner.daemon.lock().unwrap().as_ref().map(|d| d.rpc_dump()).unwrap_or_default()
+    }
+
+    /// 创建新的 RPC 客户端
+    pub fn create_rpc_client(&self) -> Option<Aria2RpcClient> {
+        self.inner.daemon.lock().unwrap().as_ref().and_then(|d| d.get_rpc_client())
+    }
+
+    /// 关闭管理器
+    pub async fn shutdown(&self) -> Aria2Result<()> {
+        let _lifecycle = self.inner.lifecycle.lock().await;
+        self.stop_locked(false).await
+    }
+
+    // 关闭守护进程，调用方需持有 lifecycle 锁；`idle` 为 true 时添加下载会自动重新启动
+    async fn stop_locked(&self, idle: bool) -> Aria2Result<()> {
+        // 先设置标志，关闭期间到达的添加请求也会等待重新启动
+        self.inner.idle_stopped.store(idle, Ordering::SeqCst);
+
+        if let Some(token) = self.inner.watcher.lock().unwrap().take() {
+            token.cancel();
+        }
+
+        let daemon = self.inner.daemon.lock().unwrap().take();
+        match daemon {
+            // 空闲关闭后还会重新启动，让 aria2 自己保存会话和控制文件
+            Some(mut daemon) if idle => daemon.stop_gracefully(Duration::from_secs(5)).await,
+            Some(mut daemon) => daemon.stop().await,
+            None => {}
+        }
+        self.inner.snapshot.lock().unwrap().clear();
+        self.save_stats();
+        tracing::info!("Aria2Manager 已关闭");
+        Ok(())
+    }
+
+    /// 检查是否运行中
+    pub fn is_running(&self) -> bool {
+        self.inner.daemon.lock().unwrap().as_ref().is_some_and(|d| d.is_running())
+    }
+
+    /// 当前 aria2 的 RPC 端口
+    pub fn rpc_port(&self) -> Option<u16> {
+        self.inner.daemon.lock().unwrap().as_ref().and_then(|d| d.port())
+    }
+
+    /// 当前守护进程的端口和重启次数，变化时说明换了一个 aria2 进程，之前设置的全局选项已丢失
+    #[cfg(feature = "pool")]
+    pub(crate) fn daemon_generation(&self) -> Option<(u16, u64)> {
+        let daemon = self.inner.daemon.lock().unwrap();
+        daemon.as_ref().and_then(|d| Some((d.port()?, d.restart_count())))
+    }
+
+    fn rpc_client(&self) -> Aria2Result<Aria2RpcClient> {
+        self.create_rpc_client()
+            .ok_or_else(|| Aria2Error::DaemonError("守护进程未运行".to_string()))
+    }
+
+    // 重新启动空闲时关闭的守护进程，并发添加时只有第一个调用会启动；启动失败时保留标志，下次添加再试
+    pub(crate) async fn restart_if_idle(&self) -> Aria2Result<()> {
+        if !self.inner.idle_stopped.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let _lifecycle = self.inner.lifecycle.lock().await;
+        if self.inner.daemon.lock().unwrap().is_some() || !self.inner.idle_stopped.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        tracing::info!("添加下载，重新启动空闲时关闭的守护进程");
+        self.start_locked().await?;
+        self.inner.idle_stopped.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 添加下载任务，配置了任务存储时同时保存任务记录
+    ///
+    /// 相同任务的去重规则见 [`Aria2RpcClient::add_uri`]。
+    pub async fn add_download(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        self.add_download_with_metadata(uris, options, HashMap::new()).await
+    }
+
+    /// 添加下载任务并附加元数据
+    pub async fn add_download_with_metadata(
+        &self,
+        uris: Vec<String>,
+        options: Option<DownloadOptions>,
+        metadata: HashMap<String, String>,
+    ) -> Aria2Result<String> {
+        self.restart_if_idle().await?;
+        let client = self.rpc_client()?;
+        self.check_supported(&client, &uris).await?;
+        let options = options.unwrap_or_default().resolve();
+
+        // 已经下载过内容相同的文件时直接返回原任务
+        #[cfg(feature = "checksum")]
+        if let Some(sha256) = options.checksum.as_deref().and_then(|c| c.strip_prefix("sha-256=")) {
+            if let Some(existing) = self.find_duplicate(sha256).await? {
+                return Ok(existing.gid);
+            }
+        }
+
+        let gid = client.add_uri(uris.clone(), Some(options.clone())).await?;
+        tracing::info!(gid = %gid, uri = %uris[0], "添加下载任务");
+        // 新任务立即可查，不必等下一轮轮询
+        if let Ok(status) = client.tell_status(&gid).await {
+            self.inner.snapshot.lock().unwrap().upsert(status);
+        }
+        let _ = self.inner.events.send(DownloadEvent::Added { gid: gid.clone() });
+        self.wake();
+
+        if let Some(store) = &self.inner.store {
+            let record = TaskRecord {
+                gid: gid.clone(),
+                uris,
+                options: DownloadOptions { gid: Some(gid.clone()), ..options },
+                metadata,
+                completed: false,
+            };
+            store.save(&record).await?;
+        }
+
+        Ok(gid)
+    }
+
+    /// 种子、磁力链接和 Metalink 需要 aria2 编译时启用相应功能，缺少时直接返回 UnsupportedType
+    async fn check_supported(&self, client: &Aria2RpcClient, uris: &[String]) -> Aria2Result<()> {
+        let required = uris.iter().find_map(|uri| {
+            let path = uri.split(['?', '#']).next().unwrap_or_default();
+            if uri.starts_with("magnet:") || path.ends_with(".torrent") {
+                Some("BitTorrent")
+            } else if path.ends_with(".metalink") || path.ends_with(".meta4") {
+                Some("Metalink")
+            } else {
+                None
+            }
+        });
+        let Some(required) = required else {
+            return Ok(());
+        };
+
+        let cached = self.inner.features.lock().unwrap().clone();
+        let features = match cached {
+            Some(features) => features,
+            None => {
+                let features = client.get_version().await?.enabled_features;
+                *self.inner.features.lock().unwrap() = Some(features.clone());
+                features
+            }
+        };
+        if !features.iter().any(|f| f == required) {
+            return Err(Aria2Error::UnsupportedType(format!("当前的 aria2 未启用 {} 功能", required)));
+        }
+        Ok(())
+    }
+
+    /// 调整任务的分片数和单服务器连接数，无需取消后重新添加
+    ///
+    /// 对正在下载的任务，aria2 会先暂停再按新选项继续，已下载的部分不会丢失。
+    pub async fn tune(&self, gid: &str, split: Option<u8>, max_connection_per_server: Option<u8>) -> Aria2Result<()> {
+        let tuning = DownloadOptions { split, max_connection_per_server, ..Default::default() };
+        tuning.validate()?;
+
+        let mut options = HashMap::new();
+        if let Some(split) = split {
+            options.insert("split".to_string(), split.to_string());
+        }
+        if let Some(n) = max_connection_per_server {
+            options.insert("max-connection-per-server".to_string(), n.to_string());
+        }
+        if options.is_empty() {
+            return Ok(());
+        }
+        self.rpc_client()?.change_option(gid, options).await?;
+
+        // 同步更新任务记录，恢复任务时沿用新的设置
+        if let Some(store) = &self.inner.store {
+            if let Some(mut record) = store.load().await?.into_iter().find(|r| r.gid == gid) {
+                record.options.split = split.or(record.options.split);
+                record.options.max_connection_per_server = max_connection_per_server.or(record.options.max_connection_per_server);
+                store.save(&record).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 查找已下载且 SHA-256 相同的任务，文件已被删除的任务不算在内
+    #[cfg(feature = "checksum")]
+    pub async fn find_duplicate(&self, sha256: &str) -> Aria2Result<Option<TaskRecord>> {
+        let sha256 = sha256.to_ascii_lowercase();
+        Ok(self.task_records().await?.into_iter().find(|record| {
+            record.completed
+                && record.metadata.get("sha256") == Some(&sha256)
+                && record.metadata.get("path").is_some_and(|p| Path::new(p).is_file())
+        }))
+    }
+
+    /// 移除下载任务及其记录
+    pub async fn remove_download(&self, gid: &str) -> Aria2Result<()> {
+        let client = self.rpc_client()?;
+        if let Ok(status) = client.tell_status(gid).await {
+            if matches!(status.status.as_str(), "active" | "waiting" | "paused") {
+                client.remove(gid).await?;
+            } else {
+                // 已停止的任务仍在 tellStopped 中，不清除结果的话下一轮轮询又会出现在快照里
+                client.remove_download_result(gid).await?;
+            }
+        }
+        self.inner.snapshot.lock().unwrap().remove(gid);
+        self.wake();
+
+        if let Some(store) = &self.inner.store {
+            store.remove(gid).await?;
+        }
+        Ok(())
+    }
+
+    /// 按 URI 和存储路径找回任务的 GID
+    ///
+    /// 调用方丢失了 GID（例如重启后没有持久化）时使用，会匹配其他工具添加的同 URI、同路径任务。
+    pub async fn find_task(&self, uris: &[String], options: Option<&DownloadOptions>) -> Aria2Result<Option<String>> {
+        let options = options.cloned().unwrap_or_default().resolve();
+        self.rpc_client()?.gid_for_task(uris, Some(&options)).await
+    }
+
+    /// 从后台监视任务的快照中读取任务进度，不发起 RPC
+    ///
+    /// 快照每个监视周期刷新一次，新添加的任务立即可查，移除的任务立即消失；守护进程未运行或任务不存在时返回 None。
+    pub fn get_progress(&self, gid: &str) -> Option<DownloadStatus> {
+        self.inner.snapshot.lock().unwrap().get(gid).cloned()
+    }
+
+    /// 从后台监视任务的快照中列出全部任务（活跃、等待、已停止），不发起 RPC
+    pub fn list_tasks(&self) -> Vec<DownloadStatus> {
+        self.inner.snapshot.lock().unwrap().tasks.clone()
+    }
+
+    /// 获取已保存的任务记录，未配置任务存储时返回空列表
+    pub async fn task_records(&self) -> Aria2Result<Vec<TaskRecord>> {
+        match &self.inner.store {
+            Some(store) => store.load().await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// 立即开始下载等待中的任务
+    ///
+    /// 把任务移到等待队列最前面并恢复暂停，并发数已满时临时把 max-concurrent-downloads 加一，
+    /// 任务结束后再恢复。
+    pub async fn download_now(&self, gid: &str) -> Aria2Result<()> {
+        let client = self.rpc_client()?;
+        let status = client.tell_status(gid).await?;
+        match status.status.as_str() {
+            "active" => return Ok(()),
+            "waiting" | "paused" => {}
+            other => return Err(Aria2Error::ConfigError(format!("任务已结束（{}）: {}", other, gid))),
+        }
+
+        client.change_position(gid, 0, "POS_SET").await?;
+        if status.status == "paused" {
+            client.unpause(gid).await?;
+        }
+
+        let max_concurrent = get_max_concurrent(&client).await?;
+        let num_active: u32 = client.get_global_stat().await?.num_active.parse().unwrap_or_default();
+        if num_active >= max_concurrent {
+            set_max_concurrent(&client, num_active + 1).await?;
+            self.inner.boosted.lock().unwrap().entry(gid.to_string()).or_insert(max_concurrent);
+        }
+        self.wake();
+        Ok(())
+    }
+
+    /// 切换到配置中名为 `name` 的限速方案
+    pub async fn apply_profile(&self, name: &str) -> Aria2Result<()> {
+        let profile = self.config().speed_profiles.into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| Aria2Error::ConfigError(format!("未知的限速方案: {}", name)))?;
+        let client = self.rpc_client()?;
+        if self.inner.base_concurrent.lock().unwrap().is_none() {
+            let current = get_max_concurrent(&client).await?;
+            *self.inner.base_concurrent.lock().unwrap() = Some(current);
+        }
+        client.change_global_option(self.profile_options(&profile)).await?;
+        tracing::info!(profile = name, "已切换限速方案");
+        *self.inner.speed_profile.lock().unwrap() = Some(profile);
+        Ok(())
+    }
+
+    /// 当前使用的限速方案，没有切换过时返回 None
+    pub fn speed_profile(&self) -> Option<SpeedProfile> {
+        self.inner.speed_profile.lock().unwrap().clone()
+    }
+
+    async fn reapply_profile(&self) {
+        let Some(profile) = self.speed_profile() else { return };
+        let Some(client) = self.create_rpc_client() else { return };
+        if let Err(e) = client.change_global_option(self.profile_options(&profile)).await {
+            tracing::warn!(profile = %profile.name, error = %e, "重新应用限速方案失败");
+        }
+    }
+
+    // 方案对应的全局选项，未指定同时下载任务数时恢复切换前的设置
+    fn profile_options(&self, profile: &SpeedProfile) -> HashMap<String, String> {
+        let mut options = profile.global_options();
+        let base = *self.inner.base_concurrent.lock().unwrap();
+        if let (None, Some(base)) = (profile.max_concurrent, base) {
+            options.insert("max-concurrent-downloads".to_string(), base.to_string());
+        }
+        options
+    }
+
+    /// 重新添加因网络问题失败的任务，返回重试的任务 GID
+    ///
+    /// 只重试超时、网络错误和域名解析失败等与连接相关的错误，沿用原来的 URI 和选项。
+    /// 单个任务重试失败时记录日志并继续处理其余任务。
+    pub async fn retry_failed(&self) -> Aria2Result<Vec<String>> {
+        let client = self.rpc_client()?;
+        let records = self.task_records().await?;
+        let mut retried = Vec::new();
+
+        for task in client.tell_stopped(0, 1000).await? {
+            if task.status != "error" || !task.task_error().is_some_and(|e| e.is_network_related()) {
+                continue;
+            }
+
+            let record = match records.iter().find(|r| r.gid == task.gid) {
+                Some(record) => record.clone(),
+                None => match record_from_aria2(&client, &task.gid).await {
+                    Some(record) => record,
+                    None => continue,
+                },
+            };
+
+            // 先确认任务能被重新添加，再移除旧结果，否则任务会从 aria2 中消失
+            if record.uris.is_empty() {
+                continue;
+            }
+            if let Err(e) = record.options.validate() {
+                tracing::warn!(gid = %task.gid, error = %e, "任务选项无效，跳过重试");
+                continue;
+            }
+
+            // 先移除旧结果，才能以同一个 GID 重新添加
+            if let Err(e) = client.remove_download_result(&task.gid).await {
+                tracing::warn!(gid = %task.gid, error = %e, "移除失败任务结果失败，跳过重试");
+                continue;
+            }
+            match client.add_uri(record.uris.clone(), Some(record.options)).await {
+                Ok(_) => {
+                    let _ = self.inner.events.send(DownloadEvent::Added { gid: task.gid.clone() });
+                    retried.push(task.gid);
+                }
+                Err(e) => {
+                    let uris = record.uris.join(" ");
+                    tracing::warn!(gid = %task.gid, uris = %uris, error = %e, "重新添加失败任务失败");
+                }
+            }
+        }
+
+        if !retried.is_empty() {
+            self.wake();
+        }
+        Ok(retried)
+    }
+
+    /// 导出完整的任务列表（状态、选项和元数据），用于备份或迁移到其他机器
+    ///
+    /// 包括任务存储中的记录，以及 aria2 中由其他工具添加的任务。
+    pub async fn export_tasks(&self) -> Aria2Result<Value> {
+        let client = self.rpc_client()?;
+
+        let mut tasks = client.tell_active().await?;
+        tasks.extend(client.tell_waiting(0, 1000).await?);
+        tasks.extend(client.tell_stopped(0, 1000).await?);
+        let mut statuses: HashMap<String, DownloadStatus> = tasks.into_iter()
+            .map(|t| (t.gid.clone(), t))
+            .collect();
+
+        let mut snapshots = Vec::new();
+        for record in self.task_records().await? {
+            let status = statuses.remove(&record.gid);
+            snapshots.push(TaskSnapshot { record, status });
+        }
+
+        // 不在任务存储中的任务，从 aria2 中读取 URI 和下载目录
+        for (gid, status) in statuses {
+            if let Some(mut record) = record_from_aria2(&client, &gid).await {
+                record.completed = status.status == "complete";
+                snapshots.push(TaskSnapshot { record, status: Some(status) });
+            }
+        }
+
+        Ok(serde_json::json!({ "version": 1, "tasks": snapshots }))
+    }
+
+    /// 导入 [`export_tasks`](Self::export_tasks) 导出的任务列表，返回重新添加的任务 GID
+    ///
+    /// 已完成的任务只保存记录，不会重新下载。
+    pub async fn import_tasks(&self, value: Value) -> Aria2Result<Vec<String>> {
+        let tasks = value.get("tasks").cloned()
+            .ok_or_else(|| Aria2Error::ConfigError("导入数据缺少 tasks 字段".to_string()))?;
+        let snapshots: Vec<TaskSnapshot> = serde_json::from_value(tasks)
+            .map_err(|e| Aria2Error::ConfigError(format!("导入数据格式错误: {}", e)))?;
+
+        let mut added = Vec::new();
+        for snapshot in snapshots {
+            let mut record = snapshot.record;
+            let finished = record.completed
+                || snapshot.status.as_ref().is_some_and(|s| s.status == "complete");
+
+            if finished {
+                record.completed = true;
+                if let Some(store) = &self.inner.store {
+                    store.save(&record).await?;
+                }
+                continue;
+            }
+
+            let gid = self.add_download_with_metadata(record.uris, Some(record.options), record.metadata).await?;
+            added.push(gid);
+        }
+
+        Ok(added)
+    }
+
+    /// 从纯文本链接列表批量添加下载，格式见 [`parse_url_list`]
+    ///
+    /// 返回每个链接及其添加结果（任务 GID 或错误），单个链接添加失败不影响其余链接。
+    pub async fn import_url_list(&self, path: &Path) -> Aria2Result<Vec<(String, Aria2Result<String>)>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Aria2Error::FileError(format!("读取 {} 失败: {}", path.display(), e)))?;
+
+        let mut results = Vec::new();
+        for (url, out) in parse_url_list(&text) {
+            let options = DownloadOptions { out, ..Default::default() };
+            let result = self.add_download(vec![url.clone()], Some(options)).await;
+            if let Err(e) = &result {
+                tracing::warn!(url = %url, error = %e, "导入链接失败");
+            }
+            results.push((url, result));
+        }
+        let added = results.iter().filter(|(_, r)| r.is_ok()).count();
+        let failed = results.len() - added;
+        tracing::info!(added, failed, path = %path.display(), "已导入链接列表");
+        Ok(results)
+    }
+
+    /// 重新添加存储中存在但 aria2 中缺失的任务（例如 aria2 在没有会话文件的情况下重启）
+    async fn restore_tasks(&self) -> Aria2Result<()> {
+        let Some(store) = &self.inner.store else {
+            return Ok(());
+        };
+        let client = self.rpc_client()?;
+
+        for record in store.load().await? {
+            if record.completed || client.tell_status(&record.gid).await.is_ok() {
+                continue;
+            }
+            match client.add_uri(record.uris.clone(), Some(record.options.clone())).await {
+                Ok(gid) => {
+                    let _ = self.inner.events.send(DownloadEvent::Added { gid });
+                }
+                Err(e) => tracing::warn!(gid = %record.gid, error = %e, "恢复任务失败"),
+            }
+        }
+        self.wake();
+        Ok(())
+    }
+
+    /// 在另一个端口上启动备用守护进程替换已放弃重启的守护进程，并从任务存储中重新添加未完成的任务
+    async fn failover(&self) -> Aria2Result<u16> {
+        let _lifecycle = self.inner.lifecycle.lock().await;
+
+        // 等待锁期间管理器可能已被关闭
+        if self.inner.watcher.lock().unwrap().is_none() {
+            return Err(Aria2Error::Cancelled);
+        }
+        let failed_port = {
+            let daemon = self.inner.daemon.lock().unwrap();
+            match daemon.as_ref() {
+                Some(d) if !d.is_running() => d.config.port,
+                _ => return Err(Aria2Error::DaemonError("守护进程无需切换".to_string())),
+            }
+        };
+
+        // 优先使用下一个端口，被占用时由系统分配空闲端口
+        let mut config = self.config();
+        config.port = failed_port.checked_add(1).unwrap_or(DEFAULT_PORT);
+        config.port_policy = PortPolicy::RandomEphemeral;
+
+        let mut daemon = Aria2Daemon::new(config);
+        daemon.start().await?;
+        let port = daemon.port().unwrap_or_default();
+        tracing::warn!(failed_port, port, "切换到备用守护进程");
+        *self.inner.daemon.lock().unwrap() = Some(daemon);
+
+        if self.inner.store.is_none() {
+            tracing::warn!("未配置任务存储，备用守护进程中没有原有的任务");
+        }
+        self.prepare_daemon().await;
+        Ok(port)
+    }
+
+    /// 累计传输量：本次启动以来、全部和按月统计
+    pub fn stats(&self) -> TransferStats {
+        self.inner.stats.lock().unwrap().clone()
+    }
+
+    fn save_stats(&self) {
+        if let Some(path) = self.config().stats_file {
+            let stats = self.inner.stats.lock().unwrap().clone();
+            if let Err(e) = stats.save(&path) {
+                tracing::warn!(error = %e, "保存传输统计失败");
+            }
+        }
+    }
+
+    /// 唤醒空闲中的后台监视任务，让新任务立即出现在快照中
+    pub(crate) fn wake(&self) {
+        self.inner.wake.notify_one();
+    }
+
+    /// 订阅下载事件
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DownloadEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// 以事件流的形式订阅下载事件
+    pub fn events(&self) -> EventStream {
+        EventStream::new(self.subscribe())
+    }
+
+    /// 查询下载历史，按结束时间从新到旧排列
+    pub fn history(&self, filter: &HistoryFilter) -> Aria2Result<Vec<HistoryEntry>> {
+        let Some(history_file) = self.config().history_file else {
+            return Ok(Vec::new());
+        };
+
+        let mut entries: Vec<HistoryEntry> = read_history(&history_file)?
+            .into_iter()
+            .filter(|e| filter.status.as_ref().is_none_or(|s| &e.status == s))
+            .filter(|e| filter.since.is_none_or(|since| e.completed_at >= since))
+            .collect();
+
+        entries.reverse();
+        if let Some(limit) = filter.limit {
+            entries.truncate(limit);
+        }
+        Ok(entries)
+    }
+
+    /// 开启或关闭下载完成/失败时的桌面通知（默认开启）
+    #[cfg(feature = "notify")]
+    pub fn set_notifications(&self, enabled: bool) {
+        self.inner.notifications.store(enabled, Ordering::SeqCst);
+    }
+
+    /// 启动后台监视任务，观察已停止的任务并处理新出现的完成/失败结果
+    fn spawn_watcher(&self, token: CancellationToken) {
+        // 只持有弱引用，管理器被丢弃后任务自动退出
+        let inner = Arc::downgrade(&self.inner);
+        let wake = self.inner.wake.clone();
+
+        tokio::spawn(async move {
+            // 上一轮快照中各任务的状态；第一轮只作为基准，不对启动前就存在的状态发事件。
+            // 任务被重试后状态会离开已停止，再次结束时能被重新观察到
+            let mut previous: Option<HashMap<String, String>> = None;
+            // 首次观察到任务处于活跃状态的时间
+            let mut started: HashMap<String, u64> = HashMap::new();
+            let mut network = route_local_addr();
+            let mut network_probed = std::time::Instant::now();
+            let mut network_backoff = NETWORK_PROBE_MAX;
+            // 上一轮因网络问题失败的任务数，增加时立即探测路由
+            let mut network_failures = 0;
+            let mut delay = Duration::ZERO;
+            let mut restarts = 0;
+            // 最近一次观察到没有未完成任务的起始时间
+            let mut idle_since: Option<std::time::Instant> = None;
+            // 各任务上一轮的已下载、已上传字节数，用于累加传输统计
+            let mut transferred: HashMap<String, (u64, u64)> = HashMap::new();
+            let mut stats_saved = std::time::Instant::now();
+            // 静默时段内被暂停的任务，不在静默时段时为 None
+            let mut quiet_paused: Option<std::collections::HashSet<String>> = None;
+            let mut quiet_hours: Vec<QuietHours> = Vec::new();
+
+            loop {
+                // 空闲时的轮询间隔可能很长，缩短到下一个静默时段边界，按时暂停和恢复下载
+                let now = unix_now();
+                let sleep = quiet_hours.iter()
+                    .filter_map(|window| window.next_boundary(now))
+                    .map(Duration::from_secs)
+                    .fold(delay, Duration::min);
+                tokio::select! {
+                    _ = token.cancelled() => break,
+                    _ = tokio::time::sleep(sleep) => {}
+                    _ = wake.notified() => {}
+                }
+
+                let Some(inner) = inner.upgrade() else { break };
+                let manager = Aria2Manager { inner };
+
+                // 守护进程重启次数达到上限后放弃，切换到备用守护进程
+                let failed = manager.inner.daemon.lock().unwrap().as_ref().is_some_and(|d| !d.is_running());
+                if failed {
+                    match manager.failover().await {
+                        Ok(port) => {
+                            restarts = 0;
+                            let _ = manager.inner.events.send(DownloadEvent::DaemonRestarted { port });
+                        }
+                        Err(e) => {
+                            tracing::error!(error = %e, "启动备用守护进程失败");
+                            delay = manager.config().poll_interval;
+                            continue;
+                        }
+                    }
+                }
+
+                let config = manager.config();
+                delay = config.idle_poll_interval;
+                quiet_hours.clone_from(&config.quiet_hours);
+                let Some(client) = manager.create_rpc_client() else { continue };
+
+                let (restart_count, port) = {
+                    let daemon = manager.inner.daemon.lock().unwrap();
+                    daemon.as_ref().map_or((0, None), |d| (d.restart_count(), d.port()))
+                };
+                if restart_count > restarts {
+                    restarts = restart_count;
+                    if let Err(e) = manager.restore_tasks().await {
+                        tracing::warn!(error = %e, "恢复任务失败");
+                    }
+                    manager.reapply_profile().await;
+                    if let Some(port) = port {
+                        let _ = manager.inner.events.send(DownloadEvent::DaemonRestarted { port });
+                    }
+                }
 
-impl Aria2RpcClient {
-    pub fn new(port: u16, secret: Option<String>) -> Self {
-        Self {
-            client: Client::new(),
-            base_url: format!("http://localhost:{}/jsonrpc", port),
-            secret,
-            request_id: Arc::new(AtomicU64::new(1)),
-        }
-    }
+                // 一次 multicall 取回全部任务，进度查询直接读取这份快照
+                let Ok(tasks) = client.tell_all().await else { continue };
 
-    async fn call_method<T, R>(&self, method: &str, params: T) -> Aria2Result<R>
-    where
-        T: Serialize,
-        R: for<'de> Deserialize<'de>,
-    {
-        let mut rpc_params = Vec::new();
+                // 网络从断开恢复或切换到其他网络时，重试因网络问题失败的任务。
+                // 出现新的失败时立即探测路由，失败任务仍在时逐步拉长探测间隔
+                if config.retry_on_reconnect {
+                    let failures = tasks.iter()
+                        .filter(|t| t.status == "error" && t.task_error().is_some_and(|e| e.is_network_related()))
+                        .count();
+                    let new_failure = failures > network_failures;
+                    network_failures = failures;
+                    if new_failure || network_probed.elapsed() >= network_backoff {
+                        let current_network = route_local_addr();
+                        if failures > 0 && current_network.is_some() && current_network != network {
+                            match manager.retry_failed().await {
+                                Ok(retried) if !retried.is_empty() => tracing::info!(count = retried.len(), "网络已恢复，重试失败任务"),
+                                Ok(_) => {}
+                                Err(e) => tracing::warn!(error = %e, "网络恢复后重试任务失败"),
+                            }
+                        }
+                        network_backoff = match failures {
+                            0 => NETWORK_PROBE_MAX,
+                            _ if new_failure => NETWORK_PROBE_MIN,
+                            _ => (network_backoff * 2).min(NETWORK_PROBE_MAX),
+                        };
+                        network = current_network;
+                        network_probed = std::time::Instant::now();
+                    }
+                }
 
-        // 添加 secret（如果配置了）
-        if let Some(secret) = &self.secret {
-            rpc_params.push(Value::String(format!("token:{}", secret)));
-        }
+                // 静默时段内暂停全部下载（包括期间新添加的），结束后只恢复由静默时段暂停的任务
+                if config.quiet_hours.iter().any(|w| w.contains(unix_now())) {
+                    if quiet_paused.is_none() {
+                        tracing::info!("进入静默时段，暂停全部下载");
+                        let _ = manager.inner.events.send(DownloadEvent::QuietHoursStarted);
+                    }
+                    let paused = quiet_paused.get_or_insert_with(Default::default);
+                    for task in tasks.iter().filter(|t| matches!(t.status.as_str(), "active" | "waiting")) {
+                        match client.pause(&task.gid).await {
+                            Ok(_) => {
+                                paused.insert(task.gid.clone());
+                            }
+                            Err(e) => tracing::warn!(gid = %task.gid, error = %e, "静默时段暂停任务失败"),
+                        }
+                    }
+                } else if let Some(paused) = quiet_paused.take() {
+                    let mut resumed = 0;
+                    for gid in &paused {
+                        if client.unpause(gid).await.is_ok() {
+                            resumed += 1;
+                        }
+                    }
+                    tracing::info!(resumed, "静默时段结束，恢复下载");
+                    let _ = manager.inner.events.send(DownloadEvent::QuietHoursEnded { resumed });
+                    delay = config.poll_interval;
+                }
 
-        // 添加其他参数
-        let param_value = serde_json::to_value(&params)
-            .map_err(|e| Aria2Error::RpcError(e.to_string()))?;
+                // 有任务在下载或排队时加快刷新，空闲时放慢
+                if tasks.iter().any(|t| matches!(t.status.as_str(), "active" | "waiting")) {
+                    delay = config.poll_interval;
+                }
 
-        // 如果参数是数组，则展开每个元素作为单独的参数
-        if let Value::Array(array) = param_value {
-            rpc_params.extend(array);
-        } else if !param_value.is_null() {
-            rpc_params.push(param_value);
-        }
+                let unfinished = tasks.iter().any(|t| matches!(t.status.as_str(), "active" | "waiting" | "paused"));
 
-        let request_id = self.request_id.fetch_add(1, Ordering::SeqCst);
-        let request = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": request_id.to_string(),
-            "method": method,
-            "params": rpc_params
-        });
+                // 按两轮快照之间的增量累加传输量；首次看到的任务只作为基准，
+                // 不计入恢复或续传的任务在此之前已传输的部分
+                let (mut downloaded, mut uploaded) = (0, 0);
+                let mut current_transferred = HashMap::with_capacity(tasks.len());
+                for task in &tasks {
+                    let now = (task.completed_length.parse().unwrap_or(0), task.upload_length.parse().unwrap_or(0));
+                    let before = transferred.get(&task.gid).copied().unwrap_or(now);
+                    downloaded += now.0.saturating_sub(before.0);
+                    uploaded += now.1.saturating_sub(before.1);
+                    current_transferred.insert(task.gid.clone(), now);
+                }
+                transferred = current_transferred;
+                if downloaded > 0 || uploaded > 0 {
+                    manager.inner.stats.lock().unwrap().record(downloaded, uploaded);
+                    if stats_saved.elapsed() >= Duration::from_secs(30) {
+                        manager.save_stats();
+                        stats_saved = std::time::Instant::now();
+                    }
+                }
 
-        let response = self.client
-            .post(&self.base_url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| Aria2Error::RpcError(e.to_string()))?;
+                let now = unix_now();
+                for task in tasks.iter().filter(|t| t.status == "active") {
+                    started.entry(task.gid.clone()).or_insert(now);
+                }
 
-        let rpc_response: Value = response.json().await
-            .map_err(|e| Aria2Error::RpcError(e.to_string()))?;
+                // 对比上一轮快照，按状态变化发出事件
+                let current = tasks.iter().map(|t| (t.gid.clone(), t.status.clone())).collect();
+                if let Some(before) = previous.replace(current) {
+                    for task in &tasks {
+                        if before.get(&task.gid) == Some(&task.status) {
+                            continue;
+                        }
+                        match task.status.as_str() {
+                            "active" => {
+                                let _ = manager.inner.events.send(DownloadEvent::Started { gid: task.gid.clone() });
+                            }
+                            "paused" => {
+                                let _ = manager.inner.events.send(DownloadEvent::Paused { gid: task.gid.clone() });
+                            }
+                            "complete" | "error" | "removed" => {
+                                let started_at = started.remove(&task.gid);
+                                manager.on_task_finished(&client, task, started_at).await;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
 
-        if let Some(error) = rpc_response.get("error") {
-            return Err(Aria2Error::RpcError(format!("服务器错误: {}", error)));
-        }
+                manager.inner.snapshot.lock().unwrap().refresh(tasks);
 
-        let result = rpc_response["result"].clone();
-        serde_json::from_value(result)
-            .map_err(|e| Aria2Error::RpcError(e.to_string()))
+                // 没有未完成的任务持续一段时间后关闭 aria2，已结束的任务都已记入历史，无需保存；
+                // 其他进程启动的 aria2 不由本管理器关闭
+                let attached = manager.inner.daemon.lock().unwrap().as_ref().is_some_and(|d| d.is_attached());
+                if let Some(timeout) = config.idle_shutdown.filter(|_| !attached) {
+                    if unfinished {
+                        idle_since = None;
+                    } else if idle_since.get_or_insert_with(std::time::Instant::now).elapsed() >= timeout {
+                        let _lifecycle = manager.inner.lifecycle.lock().await;
+                        // 等待锁期间管理器可能已被关闭
+                        if token.is_cancelled() {
+                            break;
+                        }
+                        // 上一轮查询之后可能有其他工具添加了任务，关闭前再确认一次
+                        let busy = client.tell_all().await
+                            .map_or(true, |tasks| tasks.iter().any(|t| matches!(t.status.as_str(), "active" | "waiting" | "paused")));
+                        if busy {
+                            idle_since = None;
+                            continue;
+                        }
+                        tracing::info!(idle_secs = timeout.as_secs(), "守护进程空闲，关闭 aria2");
+                        if let Err(e) = manager.stop_locked(true).await {
+                            tracing::warn!(error = %e, "空闲时关闭守护进程失败");
+                        }
+                        break;
+                    }
+                }
+            }
+        });
     }
 
-    /// 添加 URI 下载任务
-    pub async fn add_uri(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
-         // 检查是否存在相同URI和存储路径的任务
-        if let Some(existing_gid) = self.find_existing_task(&uris, &options).await? {
-            return Ok(existing_gid);
+    /// 把下载事件逐行写入事件日志，直到守护进程关闭
+    fn spawn_event_log(&self, path: PathBuf, token: CancellationToken) {
+        #[derive(Serialize)]
+        struct EventLogLine<'a> {
+            time: u64,
+            #[serde(flatten)]
+            event: &'a DownloadEvent,
         }
 
-        if let Some(opts) = options {
-            self.call_method("aria2.addUri", (uris, opts)).await
-        } else {
-            self.call_method("aria2.addUri", uris).await
-        }
+        let mut rx = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = token.cancelled() => break,
+                    event = rx.recv() => event,
+                };
+                match event {
+                    Ok(event) => {
+                        let line = EventLogLine { time: unix_now(), event: &event };
+                        if let Err(e) = append_json_line(&path, &line) {
+                            tracing::warn!(error = %e, "写入事件日志失败");
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "事件日志落后，部分事件未记录");
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
     }
 
-    /// 查找具有相同URI和存储路径的现有任务
-    async fn find_existing_task(&self, uris: &[String], options: &Option<DownloadOptions>) -> Aria2Result<Option<String>> {
-        // 获取所有任务（活跃、等待、已停止）
-        let mut all_tasks = Vec::new();
-
-        // 获取活跃任务
-        if let Ok(active) = self.tell_active().await {
-            all_tasks.extend(active);
+    /// 任务结束（完成、失败或被移除）时调用
+    async fn on_task_finished(&self, client: &Aria2RpcClient, task: &DownloadStatus, started_at: Option<u64>) {
+        match task.task_error() {
+            Some(error) => tracing::warn!(gid = %task.gid, status = %task.status, error = %error, "任务结束"),
+            None => tracing::info!(gid = %task.gid, status = %task.status, "任务结束"),
         }
 
-        // 获取等待任务
-        if let Ok(waiting) = self.tell_waiting(0, 1000).await {
-            all_tasks.extend(waiting);
+        let path = client.get_files(&task.gid).await.ok()
+            .and_then(|files| files.into_iter().find(|f| !f.path.is_empty()))
+            .map(|f| f.path)
+            .unwrap_or_default();
+
+        if let Some(history_file) = self.config().history_file {
+            let completed_at = unix_now();
+            let total_bytes = task.completed_length.parse().unwrap_or_default();
+            let entry = HistoryEntry {
+                gid: task.gid.clone(),
+                path: path.clone(),
+                status: task.status.clone(),
+                started_at,
+                completed_at,
+                total_bytes,
+                average_speed: started_at.map(|start| total_bytes / completed_at.saturating_sub(start).max(1)),
+            };
+            if let Err(e) = append_json_line(&history_file, &entry) {
+                tracing::warn!(gid = %task.gid, error = %e, "记录下载历史失败");
+            }
         }
 
-        // 获取已停止任务
-        if let Ok(stopped) = self.tell_stopped(0, 1000).await {
-            all_tasks.extend(stopped);
+        // 撤销 download_now 临时提高的并发数
+        let boosted_from = self.inner.boosted.lock().unwrap().remove(&task.gid);
+        if let Some(original) = boosted_from {
+            if let Ok(current) = get_max_concurrent(client).await {
+                let _ = set_max_concurrent(client, current.saturating_sub(1).max(original)).await;
+            }
         }
 
-        // 检查每个任务
-        for task in all_tasks {
-            if let Ok(status) = self.tell_status(&task.gid).await {
-                if self.is_same_task(&status, uris, options).await? {
-                    return Ok(Some(task.gid));
+        let event = match task.status.as_str() {
+            "complete" => DownloadEvent::Completed { gid: task.gid.clone(), path: path.clone() },
+            "error" => DownloadEvent::Failed { gid: task.gid.clone(), path: path.clone(), error: task.task_error() },
+            _ => DownloadEvent::Removed { gid: task.gid.clone() },
+        };
+        let _ = self.inner.events.send(event);
+
+        // 在管理器之外被移除的任务（如 AriaNg 或命令行）不应在重启后被恢复
+        if task.status == "removed" {
+            if let Some(store) = &self.inner.store {
+                if let Err(e) = store.remove(&task.gid).await {
+                    tracing::warn!(gid = %task.gid, error = %e, "删除任务记录失败");
                 }
             }
         }
 
-        Ok(None)
-    }
+        if task.status == "complete" {
+            if let Some(store) = &self.inner.store {
+                let records = store.load().await.unwrap_or_default();
+                if let Some(mut record) = records.into_iter().find(|r| r.gid == task.gid) {
+                    record.completed = true;
+                    let _ = store.save(&record).await;
 
-    /// 检查任务是否具有相同的URI和存储路径
-    async fn is_same_task(&self, status: &DownloadStatus, uris: &[String], options: &Option<DownloadOptions>) -> Aria2Result<bool> {
-        // 获取详细信息需要调用其他方法，这里简化比较
-        // 实际实现中可能需要调用 aria2.getFiles 等方法获取完整信息
-
-        // 比较URI（简化版本，实际可能需要更复杂的逻辑）
-        if let Ok(files) = self.get_files(&status.gid).await {
-            for file in files {
-                for uri in uris {
-                    if file.uris.iter().any(|u| u.uri == *uri) {
-                        // 比较存储路径
-                        let target_dir = options.as_ref().and_then(|o| o.dir.as_ref());
-                        if let Some(dir) = target_dir {
-                            if file.path.starts_with(dir) {
-                                return Ok(true);
-                            }
-                        } else {
-                            // 如果没有指定目录，认为是相同的（使用默认目录）
-                            return Ok(true);
-                        }
+                    // 在后台计算单文件任务的 SHA-256，写入任务元数据供去重使用
+                    #[cfg(feature = "checksum")]
+                    if client.get_files(&task.gid).await.is_ok_and(|files| files.len() == 1) {
+                        let store = store.clone();
+                        let path = path.clone();
+                        tokio::spawn(async move {
+                            let file = PathBuf::from(&path);
+                            let hash = match tokio::task::spawn_blocking(move || sha256_file(&file)).await {
+                                Ok(Ok(hash)) => hash,
+                                Ok(Err(e)) => {
+                                    tracing::warn!(gid = %record.gid, error = %e, "计算文件哈希失败");
+                                    return;
+                                }
+                                Err(_) => return,
+                            };
+                            record.metadata.insert("sha256".to_string(), hash);
+                            record.metadata.insert("path".to_string(), path);
+                            let _ = store.save(&record).await;
+                        });
                     }
                 }
             }
         }
 
-        Ok(false)
-    }
-
-    /// 获取下载状态
-    pub async fn tell_status(&self, gid: &str) -> Aria2Result<DownloadStatus> {
-        self.call_method("aria2.tellStatus", gid).await
-    }
+        #[cfg(feature = "notify")]
+        if self.inner.notifications.load(Ordering::SeqCst) {
+            let (summary, icon) = match task.status.as_str() {
+                "complete" => ("下载完成", "emblem-default"),
+                "error" => ("下载失败", "dialog-error"),
+                _ => return,
+            };
 
-    /// 获取活跃下载列表
-    pub async fn tell_active(&self) -> Aria2Result<Vec<DownloadStatus>> {
-        self.call_method("aria2.tellActive", ()).await
-    }
+            let mut name = Path::new(&path).file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| task.gid.clone());
+            if let Some(error) = task.task_error() {
+                name = format!("{}\n{}", name, error);
+            }
 
-    /// 获取等待下载列表
-    pub async fn tell_waiting(&self, offset: u32, num: u32) -> Aria2Result<Vec<DownloadStatus>> {
-        self.call_method("aria2.tellWaiting", (offset, num)).await
+            let _ = notify_rust::Notification::new()
+                .appname("BurnCloud")
+                .summary(summary)
+                .body(&name)
+                .icon(icon)
+                .show();
+        }
     }
+}
 
-    /// 获取已停止下载列表
-    pub async fn tell_stopped(&self, offset: u32, num: u32) -> Aria2Result<Vec<DownloadStatus>> {
-        self.call_method("aria2.tellStopped", (offset, num)).await
+/// 根据 aria2 中的任务信息构造任务记录，用于不在任务存储中的任务
+#[cfg(feature = "daemon")]
+async fn record_from_aria2(client: &Aria2RpcClient, gid: &str) -> Option<TaskRecord> {
+    let files = client.get_files(gid).await.ok()?;
+    let mut uris: Vec<String> = Vec::new();
+    for uri in files.iter().flat_map(|f| &f.uris) {
+        if !uris.contains(&uri.uri) {
+            uris.push(uri.uri.clone());
+        }
     }
-
-    /// 获取下载文件信息
-    pub async fn get_files(&self, gid: &str) -> Aria2Result<Vec<FileInfo>> {
-        self.call_method("aria2.getFiles", gid).await
+    if uris.is_empty() {
+        return None;
     }
 
-    /// 获取全局统计信息
-    pub async fn get_global_stat(&self) -> Aria2Result<GlobalStat> {
-        self.call_method("aria2.getGlobalStat", ()).await
-    }
+    // getOption 返回任务实际使用的全部选项（out、split 等），DownloadOptions 不支持的选项被忽略；
+    // 个别值无法表示或校验不通过时退回只保留目录
+    let options = client.get_option(gid).await.unwrap_or_default();
+    let dir = options.get("dir").cloned();
+    let options = serde_json::to_value(options).ok()
+        .and_then(|value| serde_json::from_value::<DownloadOptions>(value).ok())
+        .filter(|options| options.validate().is_ok())
+        .unwrap_or_else(|| DownloadOptions { dir, ..Default::default() });
+    Some(TaskRecord {
+        gid: gid.to_string(),
+        uris,
+        options: DownloadOptions { gid: Some(gid.to_string()), ..options },
+        metadata: HashMap::new(),
+        completed: false,
+    })
+}
 
-    /// 暂停下载
-    pub async fn pause(&self, gid: &str) -> Aria2Result<String> {
-        self.call_method("aria2.pause", gid).await
-    }
+/// 获取本机访问公网时使用的地址，没有可用路由时返回 None
+///
+/// UDP 的 connect 只查询路由表，不会发送任何数据包。先查 IPv4，纯 IPv6 网络再查 IPv6。
+#[cfg(feature = "daemon")]
+fn route_local_addr() -> Option<std::net::IpAddr> {
+    let probe = |bind: &str, target: &str| {
+        let socket = std::net::UdpSocket::bind(bind).ok()?;
+        socket.connect(target).ok()?;
+        socket.local_addr().ok().map(|addr| addr.ip())
+    };
+    probe("0.0.0.0:0", "8.8.8.8:80").or_else(|| probe("[::]:0", "[2001:4860:4860::8888]:80"))
+}
 
-    /// 恢复下载
-    pub async fn unpause(&self, gid: &str) -> Aria2Result<String> {
-        self.call_method("aria2.unpause", gid).await
-    }
+#[cfg(feature = "daemon")]
+async fn get_max_concurrent(client: &Aria2RpcClient) -> Aria2Result<u32> {
+    client.get_global_option().await?
+        .get("max-concurrent-downloads")
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Aria2Error::RpcError("无法读取 max-concurrent-downloads".to_string()))
+}
 
-    /// 移除下载
-    pub async fn remove(&self, gid: &str) -> Aria2Result<String> {
-        self.call_method("aria2.remove", gid).await
-    }
+#[cfg(feature = "daemon")]
+async fn set_max_concurrent(client: &Aria2RpcClient, value: u32) -> Aria2Result<String> {
+    let mut options = HashMap::new();
+    options.insert("max-concurrent-downloads".to_string(), value.to_string());
+    client.change_global_option(options).await
+}
 
-    /// 关闭 aria2
-    pub async fn shutdown(&self) -> Aria2Result<String> {
-        self.call_method("aria2.shutdown", ()).await
+#[cfg(feature = "daemon")]
+impl Default for Aria2Manager {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 // ============================================================================
-// 简单守护进程
+// 下载管理器接口
 // ============================================================================
 
-pub struct Aria2Daemon {
-    instance: Arc<Mutex<Option<Aria2Instance>>>,
-    config: Aria2Config,
-    is_running: Arc<AtomicBool>,
-}
-
-impl Aria2Daemon {
-    pub fn new(config: Aria2Config) -> Self {
-        Self {
-            instance: Arc::new(Mutex::new(None)),
-            config,
-            is_running: Arc::new(AtomicBool::new(false)),
-        }
-    }
+/// 界面和业务代码使用的下载管理器接口
+///
+/// [`Aria2Manager`] 是基于 aria2 的实现；开启 `testing` feature 后还有不依赖 aria2 的
+/// `testing::FakeManager`，用于在没有安装 aria2 的环境中开发和测试界面流程。
+#[async_trait::async_trait]
+pub trait DownloadManager: Send + Sync {
+    /// 添加下载任务，返回任务 GID
+    async fn add_download(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String>;
 
-    pub async fn start(&mut self) -> Aria2Result<()> {
-        if self.is_running.load(Ordering::SeqCst) {
-            return Err(Aria2Error::DaemonError("守护进程已在运行".to_string()));
-        }
+    /// 移除下载任务
+    async fn remove_download(&self, gid: &str) -> Aria2Result<()>;
 
-        let instance = start_aria2_rpc(&self.config).await?;
-        println!("aria2 RPC 服务已启动在端口: {}", instance.port);
+    /// 立即开始下载等待中或暂停的任务
+    async fn download_now(&self, gid: &str) -> Aria2Result<()>;
 
-        *self.instance.lock().unwrap() = Some(instance);
-        self.is_running.store(true, Ordering::SeqCst);
+    /// 读取任务进度，任务不存在时返回 None
+    fn get_progress(&self, gid: &str) -> Option<DownloadStatus>;
 
-        // 启动监控任务
-        let instance = Arc::clone(&self.instance);
-        let is_running = Arc::clone(&self.is_running);
-        let config = self.config.clone();
+    /// 列出全部任务
+    fn list_tasks(&self) -> Vec<DownloadStatus>;
 
-        tokio::spawn(async move {
-            while is_running.load(Ordering::SeqCst) {
-                tokio::time::sleep(Duration::from_millis(1000)).await;
+    /// 订阅下载事件
+    fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DownloadEvent>;
 
-                let need_restart = {
-                    let mut lock = instance.lock().unwrap();
-                    match lock.as_mut() {
-                        Some(inst) => !inst.is_running(), // 检查进程是否还在运行
-                        None => true,
-                    }
-                };
+    /// 检查下载后端是否可用
+    fn is_running(&self) -> bool;
+}
 
-                if need_restart {
-                    println!("检测到aria2已退出，重启中...");
-                    if let Ok(new_instance) = start_aria2_rpc(&config).await {
-                        let new_port = new_instance.port;
-                        *instance.lock().unwrap() = Some(new_instance);
-                        println!("aria2重启成功，端口: {}", new_port);
-                    }
-                }
-            }
-        });
+#[cfg(feature = "daemon")]
+#[async_trait::async_trait]
+impl DownloadManager for Aria2Manager {
+    async fn add_download(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        Aria2Manager::add_download(self, uris, options).await
+    }
 
-        Ok(())
+    async fn remove_download(&self, gid: &str) -> Aria2Result<()> {
+        Aria2Manager::remove_download(self, gid).await
     }
 
-    pub async fn stop(&mut self) {
-        self.is_running.store(false, Ordering::SeqCst);
+    async fn download_now(&self, gid: &str) -> Aria2Result<()> {
+        Aria2Manager::download_now(self, gid).await
+    }
 
-        if let Some(ref mut instance) = self.instance.lock().unwrap().as_mut() {
-            let _ = instance.kill();
-        }
+    fn get_progress(&self, gid: &str) -> Option<DownloadStatus> {
+        Aria2Manager::get_progress(self, gid)
+    }
 
-        *self.instance.lock().unwrap() = None;
-        println!("aria2 守护进程已停止");
+    fn list_tasks(&self) -> Vec<DownloadStatus> {
+        Aria2Manager::list_tasks(self)
     }
 
-    pub fn get_rpc_client(&self) -> Option<Aria2RpcClient> {
-        let lock = self.instance.lock().unwrap();
-        lock.as_ref().map(|instance| {
-            Aria2RpcClient::new(instance.port, self.config.secret.clone())
-        })
+    fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DownloadEvent> {
+        Aria2Manager::subscribe(self)
     }
 
-    pub fn is_running(&self) -> bool {
-        self.is_running.load(Ordering::SeqCst)
+    fn is_running(&self) -> bool {
+        Aria2Manager::is_running(self)
     }
 }
 
 // ============================================================================
-// 统一管理器 - 主要入口点
+// 便利函数
 // ============================================================================
 
-pub struct Aria2Manager {
-    daemon: Option<Aria2Daemon>,
-    config: Aria2Config,
+/// 快速启动 aria2 管理器
+#[cfg(feature = "daemon")]
+pub async fn quick_start() -> Aria2Result<Aria2Manager> {
+    let manager = Aria2Manager::new();
+    manager.download_and_setup().await?;
+    manager.start_daemon().await?;
+    Ok(manager)
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Aria2Manager {
-    pub fn new() -> Self {
-        Self {
-            daemon: None,
-            config: Aria2Config::default(),
-        }
-    }
+    // 2024-01-01 00:00:00 UTC
+    const MIDNIGHT: u64 = 1_704_067_200;
 
-    pub fn with_config(config: Aria2Config) -> Self {
-        Self {
-            daemon: None,
-            config,
-        }
+    fn at(hours: u64, minutes: u64) -> u64 {
+        MIDNIGHT + hours * 3600 + minutes * 60
     }
 
-    /// 下载并设置 aria2
-    pub async fn download_and_setup(&mut self) -> Aria2Result<()> {
-        println!("正在下载 aria2...");
-        let aria2_path = download_aria2().await?;
-        println!("aria2 已下载到: {:?}", aria2_path);
+    #[test]
+    fn quiet_hours_within_one_day() {
+        let window = QuietHours::new("09:00", "17:30", 0);
+        assert!(!window.contains(at(8, 59)));
+        assert!(window.contains(at(9, 0)));
+        assert!(window.contains(at(17, 29)));
+        assert!(!window.contains(at(17, 30)));
+    }
 
-        self.config.aria2_path = aria2_path;
-        Ok(())
+    #[test]
+    fn quiet_hours_across_midnight() {
+        let window = QuietHours::new("22:00", "06:00", 0);
+        assert!(window.contains(at(23, 0)));
+        assert!(window.contains(at(0, 0)));
+        assert!(window.contains(at(5, 59)));
+        assert!(!window.contains(at(6, 0)));
+        assert!(!window.contains(at(12, 0)));
     }
 
-    /// 启动守护进程
-    pub async fn start_daemon(&mut self) -> Aria2Result<()> {
-        if self.daemon.is_some() {
-            return Err(Aria2Error::DaemonError("守护进程已存在".to_string()));
-        }
+    #[test]
+    fn quiet_hours_with_utc_offset() {
+        // 北京时间 09:00-10:00 即 UTC 01:00-02:00
+        let window = QuietHours::new("09:00", "10:00", 480);
+        assert!(window.contains(at(1, 30)));
+        assert!(!window.contains(at(9, 30)));
+        // 负时差在 UTC 零点之前换日
+        let window = QuietHours::new("20:00", "21:00", -300);
+        assert!(window.contains(at(1, 0)));
+    }
 
-        let mut daemon = Aria2Daemon::new(self.config.clone());
-        daemon.start().await?;
-        self.daemon = Some(daemon);
+    #[test]
+    fn quiet_hours_rejects_invalid_times() {
+        assert!(!QuietHours::new("24:00", "06:00", 0).contains(at(1, 0)));
+        assert!(!QuietHours::new("9", "10:00", 0).contains(at(9, 30)));
+        assert!(!QuietHours::new("09:00", "09:60", 0).contains(at(9, 30)));
+        assert_eq!(QuietHours::new("xx", "06:00", 0).next_boundary(at(1, 0)), None);
+    }
 
-        println!("aria2 守护进程启动成功！");
-        Ok(())
+    #[test]
+    fn quiet_hours_next_boundary() {
+        let window = QuietHours::new("22:00", "06:00", 0);
+        assert_eq!(window.next_boundary(at(21, 0)), Some(3600));
+        assert_eq!(window.next_boundary(at(23, 0)), Some(7 * 3600));
+        // 正好处于开始时刻时，下一个边界是结束时刻
+        assert_eq!(window.next_boundary(at(22, 0)), Some(8 * 3600));
+        assert_eq!(window.next_boundary(at(21, 59) + 30), Some(30));
+        // 开始等于结束的时间段从不生效
+        assert_eq!(QuietHours::new("08:00", "08:00", 0).next_boundary(at(1, 0)), None);
     }
 
-    /// 获取 RPC 客户端
-    pub fn get_rpc_client(&self) -> Option<&Aria2RpcClient> {
-        // 由于借用检查器限制，这里简化实现
-        None
+    #[test]
+    fn url_list_skips_comments_and_blank_lines() {
+        let list = parse_url_list(
+            "# 注释\n\n  https://example.com/a.bin  \r\nhttps://example.com/b?id=1 -> b.bin\nhttps://example.com/c ->  \n",
+        );
+        assert_eq!(
+            list,
+            vec![
+                ("https://example.com/a.bin".to_string(), None),
+                ("https://example.com/b?id=1".to_string(), Some("b.bin".to_string())),
+                ("https://example.com/c".to_string(), None),
+            ]
+        );
+        assert!(parse_url_list("").is_empty());
     }
 
-    /// 创建新的 RPC 客户端
-    pub fn create_rpc_client(&self) -> Option<Aria2RpcClient> {
-        self.daemon.as_ref().and_then(|d| d.get_rpc_client())
+    #[test]
+    fn month_key_handles_month_and_year_edges() {
+        assert_eq!(month_key(0), "1970-01");
+        assert_eq!(month_key(MIDNIGHT - 1), "2023-12");
+        assert_eq!(month_key(MIDNIGHT), "2024-01");
+        // 2024 年是闰年：2 月 29 日仍属 2 月
+        assert_eq!(month_key(1_709_251_199), "2024-02");
+        assert_eq!(month_key(1_709_251_200), "2024-03");
     }
 
-    /// 关闭管理器
-    pub async fn shutdown(&mut self) -> Aria2Result<()> {
-        if let Some(ref mut daemon) = self.daemon {
-            daemon.stop().await;
-        }
-        self.daemon = None;
-        println!("Aria2Manager 已关闭");
-        Ok(())
+    #[cfg(feature = "daemon")]
+    #[test]
+    fn port_policy_handles_occupied_port() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let taken = listener.local_addr().unwrap().port();
+
+        assert!(matches!(PortPolicy::FixedOrFail.select(taken), Err(Aria2Error::PortError(_))));
+
+        let port = PortPolicy::AutoIncrement { max: 20 }.select(taken).unwrap();
+        assert!(port > taken && port <= taken.saturating_add(20));
+        assert!(matches!(
+            PortPolicy::AutoIncrement { max: 0 }.select(taken),
+            Err(Aria2Error::PortError(_))
+        ));
+
+        let port = PortPolicy::RandomEphemeral.select(taken).unwrap();
+        assert_ne!(port, taken);
+        assert!(check_port_available(port));
     }
 
-    /// 检查是否运行中
-    pub fn is_running(&self) -> bool {
-        self.daemon.as_ref().map_or(false, |d| d.is_running())
+    #[test]
+    fn content_disposition_filename() {
+        assert_eq!(parse_content_disposition("attachment; filename=\"a b.zip\""), Some("a b.zip".to_string()));
+        assert_eq!(parse_content_disposition("attachment; FILENAME=plain.bin"), Some("plain.bin".to_string()));
+        // filename* 优先于 filename，与出现顺序无关
+        assert_eq!(
+            parse_content_disposition("attachment; filename=\"fallback.zip\"; filename*=UTF-8''%E6%96%87%E4%BB%B6.zip"),
+            Some("文件.zip".to_string())
+        );
+        assert_eq!(parse_content_disposition("attachment"), None);
+        assert_eq!(parse_content_disposition("attachment; filename=\"\""), None);
+        // 编码不合法时不回退到可能被截断的名字
+        assert_eq!(parse_content_disposition("attachment; filename*=UTF-8''%E6%9"), None);
+        assert_eq!(parse_content_disposition("attachment; filename*=UTF-8''%FF"), None);
     }
-}
 
-impl Default for Aria2Manager {
-    fn default() -> Self {
-        Self::new()
+    #[cfg(all(feature = "daemon", unix))]
+    #[tokio::test]
+    async fn daemon_gives_up_when_restart_keeps_failing() {
+        let config = Aria2Config {
+            aria2_path: PathBuf::from("/nonexistent/aria2c"),
+            max_restarts: Some(2),
+            kill_existing: false,
+            ..Default::default()
+        };
+        let daemon = Aria2Daemon::new(config.clone());
+        // 一个立即退出的进程，模拟启动后崩溃的 aria2
+        let process = Command::new("true").spawn().unwrap();
+        *daemon.instance.lock().unwrap() = Some(Aria2Instance { process, port: config.port, config });
+        daemon.is_running.store(true, Ordering::SeqCst);
+        daemon.spawn_monitor();
+
+        // 每次重启都失败，达到上限后停止运行，管理器据此切换到备用守护进程
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while daemon.is_running() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("重启失败时监控任务没有放弃");
+        assert_eq!(daemon.restart_count(), 0);
+        assert!(daemon.instance.lock().unwrap().is_none());
     }
-}
 
-// ============================================================================
-// 便利函数
-// ============================================================================
+    #[cfg(feature = "daemon")]
+    #[tokio::test]
+    async fn removed_task_drops_store_record() {
+        let path = std::env::temp_dir().join(format!("burncloud-aria2-store-{}.json", std::process::id()));
+        let store = Arc::new(JsonTaskStore::new(&path));
+        for gid in ["0000000000000001", "0000000000000002"] {
+            let record = TaskRecord {
+                gid: gid.to_string(),
+                uris: vec![format!("https://example.com/{}", gid)],
+                options: DownloadOptions::default(),
+                metadata: HashMap::new(),
+                completed: false,
+            };
+            store.save(&record).await.unwrap();
+        }
 
-/// 快速启动 aria2 管理器
-pub async fn quick_start() -> Aria2Result<Aria2Manager> {
-    let mut manager = Aria2Manager::new();
-    manager.download_and_setup().await?;
-    manager.start_daemon().await?;
-    Ok(manager)
-}
\ No newline at end of file
+        let manager = Aria2Manager::with_store(Aria2Config::default(), store.clone());
+        let task: DownloadStatus = serde_json::from_value(serde_json::json!({
+            "gid": "0000000000000001",
+            "status": "removed",
+            "totalLength": "0",
+            "completedLength": "0",
+            "downloadSpeed": "0",
+        }))
+        .unwrap();
+        // 没有运行中的 aria2，查询文件路径失败不影响处理
+        let port = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+        let client = Aria2RpcClient::new(port, None);
+        manager.on_task_finished(&client, &task, None).await;
+
+        let remaining: Vec<String> = store.load().await.unwrap().into_iter().map(|r| r.gid).collect();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(remaining, vec!["0000000000000002".to_string()]);
+    }
+}
@@ -1,82 +1,519 @@
-use burncloud_download_aria2::*;
+//! burncloud-aria2 命令行工具
+//!
+//! `daemon start` 在前台启动 aria2 守护进程，其余子命令通过 RPC 连接该守护进程，
+//! 端口和密钥取自 `--port`/`--secret`（或 `--secret-file`）或 `BURNCLOUD_ARIA2_*` 环境变量。
+//!
+//! Shell 补全：`completions <shell>` 生成静态补全脚本；
+//! 在 shell 配置中加入 `source <(COMPLETE=bash burncloud-aria2)` 等则使用动态补全，
+//! pause/resume/rm 会从运行中的守护进程补全任务 GID。
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-#[tokio::main]
-async fn main() -> Aria2Result<()> {
-    println!("🚀 启动 BurnCloud Aria2 测试...");
+use burncloud_download_aria2::*;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use clap_complete::{CompleteEnv, Shell};
+use tokio::sync::broadcast::error::RecvError;
+
+#[derive(Parser)]
+#[command(name = "burncloud-aria2", version, about = "管理 BurnCloud 的 aria2 下载")]
+struct Cli {
+    /// aria2 RPC 端口
+    #[arg(long, global = true)]
+    port: Option<u16>,
+    /// aria2 RPC 密钥
+    #[arg(long, global = true)]
+    secret: Option<String>,
+    /// 从文件读取 aria2 RPC 密钥，磁力链接处理程序使用
+    #[arg(long, global = true, value_hint = clap::ValueHint::FilePath)]
+    secret_file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 添加下载
+    Add {
+        url: String,
+        /// 保存路径，可以只给文件名
+        #[arg(short, long)]
+        output: Option<String>,
+        /// 等待下载结束，开启 progress feature 时显示进度条
+        #[arg(short, long)]
+        wait: bool,
+    },
+    /// 从文本文件批量添加下载，每行一个 URL，可用 `-> 文件名` 指定文件名
+    Import {
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        file: PathBuf,
+    },
+    /// 列出全部任务
+    List,
+    /// 暂停任务
+    Pause {
+        #[arg(add = ArgValueCompleter::new(|current: &OsStr| complete_gid(current, &["active", "waiting"])))]
+        gid: String,
+    },
+    /// 恢复任务
+    Resume {
+        #[arg(add = ArgValueCompleter::new(|current: &OsStr| complete_gid(current, &["paused"])))]
+        gid: String,
+    },
+    /// 取消任务
+    Rm {
+        #[arg(add = ArgValueCompleter::new(|current: &OsStr| complete_gid(current, &["active", "waiting", "paused"])))]
+        gid: String,
+    },
+    /// 显示总体下载速度和进度
+    Stat,
+    /// 管理守护进程
+    Daemon {
+        #[command(subcommand)]
+        action: DaemonAction,
+    },
+    /// 输出 Shell 补全脚本
+    Completions { shell: Shell },
+    /// 启动守护进程并显示终端监视界面，退出界面时关闭守护进程
+    #[cfg(feature = "tui")]
+    Monitor,
+    /// 把本程序注册为系统的磁力链接处理程序
+    #[cfg(feature = "protocol-handler")]
+    Handler {
+        #[command(subcommand)]
+        action: HandlerAction,
+    },
+}
 
-    // 使用快速启动
-    let mut manager = quick_start().await?;
-    println!("✅ Aria2 管理器启动成功");
+#[cfg(feature = "protocol-handler")]
+#[derive(Subcommand)]
+enum HandlerAction {
+    /// 注册，点击磁力链接时以 `add <链接>` 启动本程序
+    Register,
+    /// 取消注册
+    Unregister,
+}
 
-    // 获取 RPC 客户端
-    if let Some(client) = manager.create_rpc_client() {
-        println!("📡 获取到 RPC 客户端");
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// 在前台启动守护进程，按 Ctrl+C 关闭
+    Start,
+    /// 关闭正在运行的守护进程
+    Stop,
+}
 
-        // 测试基本功能
-        test_basic_operations(&client).await?;
+fn main() {
+    // 由 shell 以 COMPLETE=<shell> 调用时输出补全结果后退出
+    CompleteEnv::with_factory(Cli::command).complete();
 
-        // 测试下载功能
-        test_download(&client).await?;
+    let cli = Cli::parse();
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|e| Aria2Error::DaemonError(format!("创建运行时失败: {}", e)))
+        .and_then(|runtime| runtime.block_on(run(cli)));
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
+}
 
-    // 等待一会儿让操作完成
-    tokio::time::sleep(Duration::from_secs(2)).await;
+/// 补全处于指定状态的任务 GID，连不上守护进程时不给出候选项
+fn complete_gid(current: &OsStr, statuses: &[&str]) -> Vec<CompletionCandidate> {
+    let mut config = Aria2Config::default();
+    if config.apply_env().is_err() {
+        return Vec::new();
+    }
+    let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else {
+        return Vec::new();
+    };
+    let client = Aria2RpcClient::new(config.port, config.secret);
+    let tasks = runtime.block_on(async {
+        tokio::time::timeout(Duration::from_secs(1), client.tell_all()).await
+    });
+    let Ok(Ok(tasks)) = tasks else {
+        return Vec::new();
+    };
 
-    // 关闭管理器
-    manager.shutdown().await?;
-    println!("🛑 测试完成，管理器已关闭");
+    let current = current.to_string_lossy();
+    tasks.into_iter()
+        .filter(|task| statuses.contains(&task.status.as_str()) && task.gid.starts_with(current.as_ref()))
+        .map(|task| {
+            let percent = task.percent().map_or_else(|| "--".to_string(), |p| format!("{:.1}%", p));
+            CompletionCandidate::new(task.gid).help(Some(format!("{} {}", task.status, percent).into()))
+        })
+        .collect()
+}
 
+async fn run(cli: Cli) -> Aria2Result<()> {
+    let mut config = Aria2Config::default();
+    config.apply_env()?;
+    if let Some(port) = cli.port {
+        config.port = port;
+    }
+    if cli.secret.is_some() {
+        config.secret = cli.secret;
+    } else if let Some(path) = &cli.secret_file {
+        let secret = std::fs::read_to_string(path)
+            .map_err(|e| Aria2Error::FileError(format!("读取 {} 失败: {}", path.display(), e)))?;
+        config.secret = Some(secret.trim().to_string()).filter(|s| !s.is_empty());
+    }
+    // 命令行工具保留下载历史和累计传输量，库默认不写这些文件
+    config.history_file = Some(get_burncloud_state_dir().join("history.jsonl"));
+    config.stats_file = Some(get_burncloud_state_dir().join("stats.json"));
+
+    if let Command::Daemon { action: DaemonAction::Start } = cli.command {
+        return run_daemon(config).await;
+    }
+    #[cfg(feature = "tui")]
+    if let Command::Monitor = cli.command {
+        return run_monitor(config).await;
+    }
+
+    let client = Aria2RpcClient::new(config.port, config.secret.clone());
+    match cli.command {
+        Command::Add { url, output, wait } => {
+            let options = output.map(|output| {
+                let path = Path::new(&output);
+                DownloadOptions {
+                    dir: path.parent()
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .map(|dir| dir.display().to_string()),
+                    out: path.file_name().map(|name| name.to_string_lossy().into_owned()),
+                    ..Default::default()
+                }
+            });
+            // 通过管理器添加，去重规则与库相同；等待时用它的事件和快照跟踪进度，不必反复查询任务状态
+            let manager = attach_manager(&config).await?;
+            let events = manager.subscribe();
+            let result = match manager.add_download(vec![url], options).await {
+                Ok(gid) => {
+                    println!("{}", gid);
+                    if wait {
+                        wait_for(&manager, events, &gid).await
+                    } else {
+                        Ok(())
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            manager.shutdown().await?;
+            result?;
+        }
+        Command::Import { file } => {
+            let manager = attach_manager(&config).await?;
+            let results = manager.import_url_list(&file).await;
+            manager.shutdown().await?;
+            // 单个链接失败时继续导入其余链接，最后以失败状态退出
+            let mut failed = 0;
+            for (url, result) in results? {
+                match result {
+                    Ok(gid) => println!("{}", gid),
+                    Err(e) => {
+                        eprintln!("{}: {}", url, e);
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(Aria2Error::DownloadError(format!("{} 个链接导入失败", failed)));
+            }
+        }
+        Command::List => {
+            for task in client.tell_all().await? {
+                let total = task.total_length.parse().unwrap_or(0);
+                let completed = task.completed_length.parse().unwrap_or(0);
+                let percent = task.percent().map_or_else(|| "--".to_string(), |p| format!("{:.1}%", p));
+                println!(
+                    "{}  {:<8} {:>6}  {:>10} / {:<10} {:>12}  {}",
+                    task.gid,
+                    task.status,
+                    percent,
+                    format::size(completed),
+                    format::size(total),
+                    format::speed(task.download_speed.parse().unwrap_or(0)),
+                    task.eta().map_or_else(|| "--".to_string(), format::duration),
+                );
+            }
+        }
+        Command::Pause { gid } => {
+            client.pause(&gid).await?;
+        }
+        Command::Resume { gid } => {
+            client.unpause(&gid).await?;
+        }
+        Command::Rm { gid } => {
+            let manager = attach_manager(&config).await?;
+            let result = manager.remove_download(&gid).await;
+            manager.shutdown().await?;
+            result?;
+        }
+        Command::Stat => {
+            let progress = client.global_progress().await?;
+            println!("活跃任务: {}", progress.num_active);
+            println!("下载速度: {}", format::speed(progress.download_speed));
+            println!("上传速度: {}", format::speed(progress.upload_speed));
+            println!(
+                "进度: {} / {} ({:.1}%)，剩余 {}",
+                format::size(progress.downloaded_bytes),
+                format::size(progress.total_bytes),
+                progress.percent(),
+                progress.eta().map_or_else(|| "--".to_string(), format::duration),
+            );
+        }
+        Command::Daemon { action: DaemonAction::Stop } => {
+            stop_daemon(&client, config.port).await?;
+            println!("守护进程已关闭");
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "burncloud-aria2", &mut std::io::stdout());
+        }
+        Command::Daemon { action: DaemonAction::Start } => unreachable!(),
+        #[cfg(feature = "tui")]
+        Command::Monitor => unreachable!(),
+        #[cfg(feature = "protocol-handler")]
+        Command::Handler { action } => {
+            let exe = std::env::current_exe()
+                .map_err(|e| Aria2Error::FileError(format!("获取程序路径失败: {}", e)))?;
+            match action {
+                HandlerAction::Register => {
+                    // 密钥不写入注册表或 .desktop 文件，保存在只有当前用户可读的文件中
+                    let port = cli.port.map(|port| port.to_string());
+                    let secret_file = protocol::save_handler_secret(config.secret.as_deref())?
+                        .map(|path| path.display().to_string());
+                    let mut args = Vec::new();
+                    if let Some(port) = &port {
+                        args.extend(["--port", port.as_str()]);
+                    }
+                    if let Some(path) = &secret_file {
+                        args.extend(["--secret-file", path.as_str()]);
+                    }
+                    args.push("add");
+                    protocol::register_magnet_handler(&exe, &args)?;
+                    println!("已注册磁力链接处理程序");
+                }
+                HandlerAction::Unregister => {
+                    protocol::unregister_magnet_handler(&exe)?;
+                    protocol::save_handler_secret(None)?;
+                    println!("已取消注册磁力链接处理程序");
+                }
+            }
+        }
+    }
     Ok(())
 }
 
-async fn test_basic_operations(client: &Aria2RpcClient) -> Aria2Result<()> {
-    println!("🔍 测试基本操作...");
+/// 连接到已在运行的守护进程，用于跟踪任务
+///
+/// 历史、统计、通知、静默时段和网络恢复重试由 `daemon start` 进程负责，这里全部关闭，避免重复处理。
+async fn attach_manager(config: &Aria2Config) -> Aria2Result<Aria2Manager> {
+    let config = Aria2Config {
+        history_file: None,
+        stats_file: None,
+        event_log: None,
+        idle_shutdown: None,
+        retry_on_reconnect: false,
+        quiet_hours: Vec::new(),
+        ..config.clone()
+    };
+    let manager = Aria2Manager::with_config(config);
+    #[cfg(feature = "notify")]
+    manager.set_notifications(false);
+    manager.attach_daemon().await?;
+    Ok(manager)
+}
+
+/// 等待任务结束：完成和失败来自管理器的事件，进度来自快照，失败或被取消时返回错误
+async fn wait_for(
+    manager: &Aria2Manager,
+    mut events: tokio::sync::broadcast::Receiver<DownloadEvent>,
+    gid: &str,
+) -> Aria2Result<()> {
+    #[cfg(feature = "progress")]
+    let bar = indicatif::ProgressBar::new(0).with_style(
+        indicatif::ProgressStyle::with_template("[{bar:40}] {bytes}/{total_bytes} {binary_bytes_per_sec} {eta}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    let failed = |reason: String| {
+        #[cfg(feature = "progress")]
+        bar.abandon();
+        Aria2Error::DownloadError(format!("{}: {}", gid, reason))
+    };
 
-    // 获取全局统计
-    if let Ok(stat) = client.get_global_stat().await {
-        println!("  - 活跃下载: {}", stat.num_active);
-        println!("  - 等待下载: {}", stat.num_waiting);
-        println!("  - 下载速度: {}", stat.download_speed);
-    }
+    let mut ticker = tokio::time::interval(Duration::from_millis(500));
+    loop {
+        let status = tokio::select! {
+            event = events.recv() => {
+                match event {
+                    Ok(DownloadEvent::Completed { gid: done, .. }) if done == gid => "complete".to_string(),
+                    Ok(DownloadEvent::Failed { gid: done, error, .. }) if done == gid => {
+                        return Err(failed(error.map_or_else(|| "error".to_string(), |e| e.to_string())));
+                    }
+                    Ok(DownloadEvent::Removed { gid: done }) if done == gid => "removed".to_string(),
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Err(Aria2Error::DaemonError("管理器已关闭".to_string())),
+                }
+            }
+            _ = ticker.tick() => {
+                let Some(status) = manager.get_progress(gid) else { continue };
+                #[cfg(feature = "progress")]
+                {
+                    bar.set_length(status.total_length.parse().unwrap_or(0));
+                    bar.set_position(status.completed_length.parse().unwrap_or(0));
+                }
+                // 监视任务第一次查询时任务可能已经结束，这时不会再有事件，按快照判断
+                match status.status.as_str() {
+                    "error" => {
+                        return Err(failed(status.task_error().map_or(status.status, |e| e.to_string())));
+                    }
+                    _ => status.status,
+                }
+            }
+        };
 
-    // 获取活跃任务
-    if let Ok(active) = client.tell_active().await {
-        println!("  - 当前活跃任务数: {}", active.len());
+        match status.as_str() {
+            "complete" => {
+                #[cfg(feature = "progress")]
+                {
+                    if let Some(status) = manager.get_progress(gid) {
+                        bar.set_position(status.completed_length.parse().unwrap_or(0));
+                    }
+                    bar.finish();
+                }
+                return Ok(());
+            }
+            "removed" => return Err(failed(status)),
+            _ => {}
+        }
     }
+}
 
-    Ok(())
+// `daemon start` 的进程 ID 文件，`daemon stop` 据此结束前台进程
+fn pid_file(port: u16) -> PathBuf {
+    get_burncloud_state_dir().join(format!("daemon-{}.pid", port))
 }
 
-async fn test_download(client: &Aria2RpcClient) -> Aria2Result<()> {
-    println!("📥 测试下载功能...");
+/// 启动守护进程并写入进程 ID 文件，返回管理器、进程 ID 文件和 RPC 端口
+async fn start_daemon(mut config: Aria2Config) -> Aria2Result<(Aria2Manager, PathBuf, u16)> {
+    // 其他子命令按配置的端口连接，端口被占用时不能换用其他端口
+    config.port_policy = PortPolicy::FixedOrFail;
+    let pid_file = pid_file(config.port);
+    let manager = Aria2Manager::with_config(config);
+    manager.download_and_setup().await?;
+    manager.start_daemon().await?;
+    let Some(port) = manager.rpc_port() else {
+        manager.shutdown().await?;
+        return Err(Aria2Error::DaemonError("aria2 启动后没有可用的 RPC 端口".to_string()));
+    };
+    if let Some(dir) = pid_file.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(e) = std::fs::write(&pid_file, std::process::id().to_string()) {
+        manager.shutdown().await?;
+        return Err(Aria2Error::FileError(format!("写入 {} 失败: {}", pid_file.display(), e)));
+    }
+    Ok((manager, pid_file, port))
+}
 
-    // 添加一个小文件下载测试
-    let test_url = "https://mirrors.tuna.tsinghua.edu.cn/ubuntu-releases/20.04.6/ubuntu-20.04.6-live-server-amd64.iso";
+async fn run_daemon(config: Aria2Config) -> Aria2Result<()> {
+    let (manager, pid_file, port) = start_daemon(config).await?;
+    println!("守护进程已启动，RPC 端口 {}，按 Ctrl+C 关闭", port);
 
-    let options = DownloadOptions {
-        dir: Some("./downloads".to_string()),
-        out: None,
-        split: None,
-        max_connection_per_server: None,
-        continue_download: None,
+    terminated().await;
+    let _ = std::fs::remove_file(&pid_file);
+    manager.shutdown().await
+}
+
+#[cfg(feature = "tui")]
+async fn run_monitor(config: Aria2Config) -> Aria2Result<()> {
+    let (manager, pid_file, _) = start_daemon(config).await?;
+    let result = tokio::select! {
+        result = tui::run(&manager) => result,
+        _ = terminated() => {
+            ratatui::restore();
+            Ok(())
+        }
     };
-    match client.add_uri(vec![test_url.to_string()], Some(options)).await {
-        Ok(gid) => {
-            println!("  - 添加下载任务成功，GID: {}", gid);
-
-            // 等待一会儿
-            tokio::time::sleep(Duration::from_secs(1)).await;
-
-            // 检查下载状态
-            if let Ok(status) = client.tell_status(&gid).await {
-                println!("  - 任务状态: {}", status.status);
-                println!("  - 总大小: {}", status.total_length);
-                println!("  - 已完成: {}", status.completed_length);
+    let _ = std::fs::remove_file(&pid_file);
+    manager.shutdown().await?;
+    result
+}
+
+#[cfg(unix)]
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// 结束 `daemon start` 进程；直接对 aria2 调用 shutdown 会被管理器当作意外退出而重启
+async fn stop_daemon(client: &Aria2RpcClient, port: u16) -> Aria2Result<()> {
+    let pid_file = pid_file(port);
+    let pid = std::fs::read_to_string(&pid_file).ok();
+    let pid = pid.as_deref().map(str::trim).filter(|pid| !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit()));
+    if let Some(pid) = pid.filter(|pid| !is_own_process(pid)) {
+        // 崩溃或重启后遗留的 PID 文件，该 PID 可能已被其他程序占用
+        eprintln!("进程 {} 不是本程序，忽略过期的 PID 文件", pid);
+        let _ = std::fs::remove_file(&pid_file);
+    } else if let Some(pid) = pid {
+        // Unix 上发送 SIGTERM 让前台进程正常关闭 aria2；Windows 上结束前台进程后再关闭 aria2
+        #[cfg(unix)]
+        let status = std::process::Command::new("kill").args(["-TERM", pid]).status();
+        #[cfg(not(unix))]
+        let status = std::process::Command::new("taskkill").args(["/F", "/PID", pid]).status();
+        if !status.is_ok_and(|s| s.success()) {
+            eprintln!("结束守护进程 {} 失败", pid);
+        }
+        let _ = std::fs::remove_file(&pid_file);
+
+        for _ in 0..50 {
+            if matches!(client.get_version().await, Err(e) if !matches!(e, Aria2Error::Unauthorized(_))) {
+                return Ok(());
             }
+            tokio::time::sleep(Duration::from_millis(100)).await;
         }
-        Err(e) => println!("  - 添加下载任务失败: {}", e),
     }
 
+    // 不是由 `daemon start` 启动的 aria2，或前台进程已被强制结束
+    client.shutdown().await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 检查 `pid` 对应的进程是否运行的是本程序
+fn is_own_process(pid: &str) -> bool {
+    let Ok(exe) = std::env::current_exe() else { return false };
+
+    #[cfg(target_os = "linux")]
+    {
+        let running = std::fs::read_link(format!("/proc/{}/exe", pid));
+        running.is_ok_and(|running| running.canonicalize().unwrap_or(running) == exe.canonicalize().unwrap_or(exe))
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        let output = std::process::Command::new("ps").args(["-p", pid, "-o", "comm="]).output();
+        let name = exe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        output.is_ok_and(|o| o.status.success() && String::from_utf8_lossy(&o.stdout).trim().ends_with(&name))
+    }
+    #[cfg(not(unix))]
+    {
+        let filter = format!("PID eq {}", pid);
+        let output = std::process::Command::new("tasklist").args(["/FI", &filter, "/FO", "CSV", "/NH"]).output();
+        let name = exe.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+        output.is_ok_and(|o| String::from_utf8_lossy(&o.stdout).to_lowercase().contains(&format!("\"{}\"", name)))
+    }
+}
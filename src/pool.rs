@@ -0,0 +1,459 @@
+//! # 多守护进程
+//!
+//! 单个 aria2 进程同时处理几十个大文件的 HTTP 下载时会受限于 CPU 和磁盘 IO，
+//! [`Aria2Pool`] 启动多个 aria2 进程，把新任务分配给当前负载最低的进程，
+//! 并对外提供与 [`Aria2Manager`] 相似的接口。
+//!
+//! 也可以按 [`DaemonProfile`] 为 HTTP 和 BitTorrent 下载分别配置守护进程（目录、选项、端口不同），
+//! 避免开启 DHT 和做种的种子流量与对延迟敏感的 HTTP 模型下载共用一个进程和限速。
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{
+    download_aria2, Aria2Config, Aria2Error, Aria2Manager, Aria2Result, CancellationToken, DownloadOptions,
+    DownloadStatus, GlobalProgress,
+};
+
+// 每个守护进程至少分到的带宽，aria2 的限速为 0 表示不限制，不能分到 0
+const MIN_DAEMON_LIMIT: u64 = 16 * 1024;
+
+// 路由表的最大条目数，超出时淘汰最久未使用的条目
+const MAX_ROUTES: usize = 10_000;
+
+/// 守护进程负责的下载类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileKind {
+    /// HTTP/HTTPS/FTP 等普通下载
+    Http,
+    /// 磁力链接和种子
+    BitTorrent,
+    /// 任意下载
+    Any,
+}
+
+impl ProfileKind {
+    /// 按下载地址判断类型，磁力链接和 .torrent 地址属于 BitTorrent
+    pub fn of(uri: &str) -> Self {
+        let path = uri.split(['?', '#']).next().unwrap_or_default();
+        if uri.starts_with("magnet:") || path.ends_with(".torrent") {
+            Self::BitTorrent
+        } else {
+            Self::Http
+        }
+    }
+
+    fn accepts(&self, kind: ProfileKind) -> bool {
+        *self == Self::Any || *self == kind
+    }
+}
+
+/// 一个命名的守护进程配置
+#[derive(Debug, Clone)]
+pub struct DaemonProfile {
+    pub name: String,
+    pub kind: ProfileKind,
+    pub config: Aria2Config,
+}
+
+impl DaemonProfile {
+    pub fn new(name: impl Into<String>, kind: ProfileKind, config: Aria2Config) -> Self {
+        Self { name: name.into(), kind, config }
+    }
+
+    /// HTTP 下载专用：关闭 DHT 和本地节点发现产生的后台流量
+    pub fn http(config: Aria2Config) -> Self {
+        let mut config = config;
+        config.extra_args.extend(["--enable-dht=false", "--enable-dht6=false", "--bt-enable-lpd=false"].map(String::from));
+        Self::new("http", ProfileKind::Http, config)
+    }
+
+    /// BitTorrent 下载专用，使用 aria2 默认的 DHT 和做种设置
+    pub fn bittorrent(config: Aria2Config) -> Self {
+        Self::new("bittorrent", ProfileKind::BitTorrent, config)
+    }
+}
+
+/// 由多个 aria2 守护进程组成的下载池
+pub struct Aria2Pool {
+    members: Vec<Aria2Manager>,
+    profiles: Vec<(String, ProfileKind)>,
+    // 任务 GID 到所在守护进程下标的映射
+    routes: Mutex<Routes>,
+}
+
+/// 路由表，记录每个条目最近一次使用的时间以便按 LRU 淘汰
+#[derive(Debug, Default)]
+struct Routes {
+    entries: HashMap<String, (usize, u64)>,
+    clock: u64,
+}
+
+impl Routes {
+    fn get(&mut self, gid: &str) -> Option<usize> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(gid).map(|(index, used)| {
+            *used = clock;
+            *index
+        })
+    }
+
+    fn insert(&mut self, gid: String, index: usize) {
+        self.clock += 1;
+        self.entries.insert(gid, (index, self.clock));
+        if self.entries.len() > MAX_ROUTES {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(gid, _)| gid.clone());
+            if let Some(gid) = oldest {
+                self.entries.remove(&gid);
+            }
+        }
+    }
+
+    fn remove(&mut self, gid: &str) {
+        self.entries.remove(gid);
+    }
+}
+
+impl Aria2Pool {
+    /// 按给定的配置创建下载池，每个配置对应一个可处理任意下载的 aria2 进程
+    ///
+    /// 各进程的首选端口必须不同；`kill_existing` 会被关闭，否则后启动的进程会结束先启动的。
+    pub fn new(configs: Vec<Aria2Config>) -> Aria2Result<Self> {
+        let profiles = configs.into_iter().enumerate()
+            .map(|(i, config)| DaemonProfile::new(format!("daemon-{}", i), ProfileKind::Any, config))
+            .collect();
+        Self::with_profiles(profiles)
+    }
+
+    /// 按守护进程配置创建下载池，新任务只会分配给类型匹配的守护进程
+    pub fn with_profiles(profiles: Vec<DaemonProfile>) -> Aria2Result<Self> {
+        if profiles.is_empty() {
+            return Err(Aria2Error::ConfigError("下载池至少需要一个守护进程".to_string()));
+        }
+        let mut ports: Vec<u16> = profiles.iter().map(|p| p.config.port).collect();
+        ports.sort_unstable();
+        ports.dedup();
+        if ports.len() != profiles.len() {
+            return Err(Aria2Error::ConfigError("下载池中的守护进程端口不能重复".to_string()));
+        }
+
+        // 共用同一个统计文件的守护进程会互相覆盖累计值，改为按名称区分的文件
+        let stats_files: Vec<_> = profiles.iter().map(|p| p.config.stats_file.clone()).collect();
+        let shared = |path: &std::path::Path| stats_files.iter().flatten().filter(|p| *p == path).count() > 1;
+
+        let mut members = Vec::with_capacity(profiles.len());
+        let mut names = Vec::with_capacity(profiles.len());
+        for DaemonProfile { name, kind, mut config } in profiles {
+            config.kill_existing = false;
+            if let Some(path) = config.stats_file.take() {
+                config.stats_file = Some(if shared(&path) {
+                    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+                    path.with_file_name(format!("{}-{}.json", stem, name))
+                } else {
+                    path
+                });
+            }
+            members.push(Aria2Manager::with_config(config));
+            names.push((name, kind));
+        }
+        Ok(Self { members, profiles: names, routes: Mutex::new(Routes::default()) })
+    }
+
+    /// 用同一份配置创建 `size` 个守护进程，端口从 `config.port` 起依次递增
+    pub fn with_size(config: Aria2Config, size: usize) -> Aria2Result<Self> {
+        let configs = (0..size)
+            .map(|i| {
+                let port = u16::try_from(i).ok()
+                    .and_then(|i| config.port.checked_add(i))
+                    .ok_or_else(|| Aria2Error::PortError("下载池端口超出范围".to_string()))?;
+                Ok(Aria2Config { port, ..config.clone() })
+            })
+            .collect::<Aria2Result<Vec<_>>>()?;
+        Self::new(configs)
+    }
+
+    /// 池中的各个守护进程
+    pub fn members(&self) -> &[Aria2Manager] {
+        &self.members
+    }
+
+    /// 按名称查找守护进程
+    pub fn member(&self, name: &str) -> Option<&Aria2Manager> {
+        self.profiles.iter().position(|(n, _)| n == name).map(|i| &self.members[i])
+    }
+
+    /// 下载 aria2（只下载一次）并启动所有守护进程，任意一个启动失败时关闭已启动的进程
+    pub async fn start(&self) -> Aria2Result<()> {
+        if self.members.iter().any(|m| !m.config().aria2_path.exists()) {
+            let path = download_aria2().await?;
+            for member in &self.members {
+                member.set_aria2_path(path.clone());
+            }
+        }
+
+        for member in &self.members {
+            if let Err(e) = member.start_daemon().await {
+                self.shutdown().await;
+                return Err(e);
+            }
+        }
+        tracing::info!(count = self.members.len(), "下载池已启动");
+        Ok(())
+    }
+
+    /// 关闭所有守护进程
+    pub async fn shutdown(&self) {
+        for member in &self.members {
+            if let Err(e) = member.shutdown().await {
+                tracing::warn!(error = %e, "关闭守护进程失败");
+            }
+        }
+        *self.routes.lock().unwrap() = Routes::default();
+    }
+
+    /// 把下载分配给类型匹配、活跃和等待任务最少的守护进程
+    pub async fn add_download(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        let kind = uris.first().map_or(ProfileKind::Http, |uri| ProfileKind::of(uri));
+        let index = self.least_loaded(kind).await?;
+        let gid = self.members[index].add_download(uris, options).await?;
+        tracing::debug!(gid = %gid, daemon = index, "分配下载任务");
+        let mut routes = self.routes.lock().unwrap();
+        self.evict_finished(&mut routes);
+        routes.insert(gid.clone(), index);
+        Ok(gid)
+    }
+
+    /// 移除已结束或已从 aria2 中清除的任务，这些任务仍可通过各进程的快照找到
+    fn evict_finished(&self, routes: &mut Routes) {
+        routes.entries.retain(|gid, (index, _)| {
+            self.members[*index].get_progress(gid)
+                .is_some_and(|s| matches!(s.status.as_str(), "active" | "waiting" | "paused"))
+        });
+    }
+
+    /// 查询任务所在守护进程的快照
+    pub fn get_progress(&self, gid: &str) -> Option<DownloadStatus> {
+        self.member_of(gid).and_then(|m| m.get_progress(gid))
+    }
+
+    /// 列出所有守护进程中的任务
+    pub fn list_tasks(&self) -> Vec<DownloadStatus> {
+        self.members.iter().flat_map(|m| m.list_tasks()).collect()
+    }
+
+    /// 汇总所有守护进程中活跃任务的进度，每个进程一次 multicall
+    ///
+    /// 未运行或无法连接的守护进程不计入。
+    pub async fn global_progress(&self) -> GlobalProgress {
+        let mut total = GlobalProgress::default();
+        for (index, member) in self.members.iter().enumerate() {
+            let Some(client) = member.create_rpc_client() else {
+                continue;
+            };
+            match client.global_progress().await {
+                Ok(progress) => {
+                    total.total_bytes += progress.total_bytes;
+                    total.downloaded_bytes += progress.downloaded_bytes;
+                    total.download_speed += progress.download_speed;
+                    total.upload_speed += progress.upload_speed;
+                    total.num_active += progress.num_active;
+                }
+                Err(e) => tracing::warn!(daemon = index, error = %e, "获取守护进程进度失败"),
+            }
+        }
+        total
+    }
+
+    /// 取消任务
+    pub async fn remove_download(&self, gid: &str) -> Aria2Result<()> {
+        let member = match self.member_of(gid) {
+            Some(member) => member,
+            None => self.locate(gid).await
+                .ok_or_else(|| Aria2Error::RpcError(format!("任务不存在: {}", gid)))?,
+        };
+        member.remove_download(gid).await?;
+        self.routes.lock().unwrap().remove(gid);
+        Ok(())
+    }
+
+    /// 按 URI 和存储路径在各守护进程中找回任务的 GID，并记入路由表
+    pub async fn find_task(&self, uris: &[String], options: Option<&DownloadOptions>) -> Aria2Result<Option<String>> {
+        for (index, member) in self.members.iter().enumerate() {
+            if !member.is_running() {
+                continue;
+            }
+            if let Some(gid) = member.find_task(uris, options).await? {
+                self.routes.lock().unwrap().insert(gid.clone(), index);
+                return Ok(Some(gid));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 快照中还没有该任务时（如守护进程刚重启）逐个向 aria2 查询
+    async fn locate(&self, gid: &str) -> Option<&Aria2Manager> {
+        for (index, member) in self.members.iter().enumerate() {
+            let Some(client) = member.create_rpc_client() else { continue };
+            if client.tell_status(gid).await.is_ok() {
+                self.routes.lock().unwrap().insert(gid.to_string(), index);
+                return Some(member);
+            }
+        }
+        None
+    }
+
+    /// 任务所在的守护进程，不在路由表中时（如由其他工具添加）从各进程的快照中查找
+    pub fn member_of(&self, gid: &str) -> Option<&Aria2Manager> {
+        let index = self.routes.lock().unwrap().get(gid);
+        match index {
+            Some(index) => self.members.get(index),
+            None => self.members.iter().find(|m| m.get_progress(gid).is_some()),
+        }
+    }
+
+    /// 让所有守护进程的下载速度之和不超过 `total`（字节/秒），直到令牌被取消
+    ///
+    /// 每隔 `interval` 读取各守护进程最近的下载速度并重新分配 max-overall-download-limit：
+    /// 没有活跃任务的进程只保留很小的额度，用不满额度的进程让出多余部分给跑满额度的进程。
+    pub async fn run_bandwidth_budget(&self, total: u64, interval: Duration, token: CancellationToken) {
+        let mut limits: Vec<u64> = vec![0; self.members.len()];
+        let mut generations = vec![None; self.members.len()];
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = ticker.tick() => {}
+            }
+
+            let mut usage = Vec::with_capacity(self.members.len());
+            for member in &self.members {
+                let stat = match member.create_rpc_client() {
+                    Some(client) => client.get_global_stat().await.ok(),
+                    None => None,
+                };
+                usage.push(stat.map(|s| {
+                    (s.download_speed.parse().unwrap_or(0), s.num_active.parse::<u64>().unwrap_or(0) > 0)
+                }));
+            }
+
+            // 重启或切换到备用守护进程后 aria2 没有之前设置的限速，需要重新下发
+            for (index, member) in self.members.iter().enumerate() {
+                let generation = member.daemon_generation();
+                if generation != generations[index] {
+                    generations[index] = generation;
+                    limits[index] = 0;
+                }
+            }
+
+            let budget = split_budget(total, &usage, &limits);
+            for (index, (member, limit)) in self.members.iter().zip(budget).enumerate() {
+                // 变化不到 10% 时不调整，避免频繁修改全局选项
+                if limits[index].abs_diff(limit) * 10 < limits[index] {
+                    continue;
+                }
+                let Some(client) = member.create_rpc_client() else { continue };
+                let options = HashMap::from([("max-overall-download-limit".to_string(), limit.to_string())]);
+                match client.change_global_option(options).await {
+                    Ok(_) => limits[index] = limit,
+                    Err(e) => tracing::warn!(daemon = index, error = %e, "调整守护进程限速失败"),
+                }
+            }
+        }
+    }
+
+    async fn least_loaded(&self, kind: ProfileKind) -> Aria2Result<usize> {
+        let mut best: Option<(usize, u64)> = None;
+        for (index, member) in self.members.iter().enumerate() {
+            if !self.profiles[index].1.accepts(kind) {
+                continue;
+            }
+            let Some(client) = member.create_rpc_client() else {
+                continue;
+            };
+            let load = match client.get_global_stat().await {
+                Ok(stat) => stat.num_active.parse::<u64>().unwrap_or(0) + stat.num_waiting.parse::<u64>().unwrap_or(0),
+                Err(e) => {
+                    tracing::warn!(daemon = index, error = %e, "获取守护进程负载失败");
+                    continue;
+                }
+            };
+            if best.is_none_or(|(_, best_load)| load < best_load) {
+                best = Some((index, load));
+            }
+        }
+        best.map(|(index, _)| index)
+            .ok_or_else(|| Aria2Error::DaemonError(format!("下载池中没有可处理 {:?} 下载的守护进程", kind)))
+    }
+}
+
+/// 按最近的用量分配带宽
+///
+/// `usage` 为各守护进程的 (下载速度, 是否有活跃任务)，无法连接的为 None；`limits` 为当前的限速。
+fn split_budget(total: u64, usage: &[Option<(u64, bool)>], limits: &[u64]) -> Vec<u64> {
+    let active: Vec<usize> = (0..usage.len()).filter(|&i| usage[i].is_some_and(|(_, active)| active)).collect();
+    if active.is_empty() {
+        let share = (total / usage.len().max(1) as u64).max(MIN_DAEMON_LIMIT);
+        return vec![share; usage.len()];
+    }
+
+    let mut budget = vec![MIN_DAEMON_LIMIT; usage.len()];
+    let mut remaining = total.saturating_sub(MIN_DAEMON_LIMIT * (usage.len() - active.len()) as u64);
+    let share = remaining / active.len() as u64;
+
+    // 速度明显低于当前额度的进程只保留略高于实际用量的额度
+    let mut hungry = Vec::new();
+    for &i in &active {
+        let speed = usage[i].map_or(0, |(speed, _)| speed);
+        let limit = if limits[i] == 0 { share } else { limits[i] };
+        if speed * 10 < limit * 8 {
+            budget[i] = (speed + speed / 4).clamp(MIN_DAEMON_LIMIT, share.max(MIN_DAEMON_LIMIT));
+            remaining = remaining.saturating_sub(budget[i]);
+        } else {
+            hungry.push(i);
+        }
+    }
+
+    // 剩余额度平分给跑满额度的进程
+    if !hungry.is_empty() {
+        let share = (remaining / hungry.len() as u64).max(MIN_DAEMON_LIMIT);
+        for i in hungry {
+            budget[i] = share;
+        }
+    }
+    budget
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: u64 = 1024;
+
+    #[test]
+    fn split_budget_evenly_when_idle() {
+        assert_eq!(split_budget(1024 * K, &[None, Some((0, false)), None, None], &[0; 4]), vec![256 * K; 4]);
+        // 总额度过小时每个进程仍保留最低额度
+        assert_eq!(split_budget(K, &[Some((0, false)); 2], &[0; 2]), vec![MIN_DAEMON_LIMIT; 2]);
+        assert!(split_budget(1024 * K, &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn split_budget_moves_unused_share_to_busy_daemons() {
+        let usage = [Some((100 * K, true)), Some((480 * K, true)), None];
+        let budget = split_budget(1000 * K, &usage, &[500 * K, 500 * K, 0]);
+
+        // 慢的进程只保留 1.25 倍实际速度，空闲进程保留最低额度，其余都给跑满额度的进程
+        assert_eq!(budget, vec![125 * K, 1000 * K - MIN_DAEMON_LIMIT - 125 * K, MIN_DAEMON_LIMIT]);
+        assert_eq!(budget.iter().sum::<u64>(), 1000 * K);
+    }
+
+    #[test]
+    fn split_budget_never_goes_below_minimum() {
+        let budget = split_budget(0, &[Some((0, false)), Some((50 * K, true))], &[0, 0]);
+        assert_eq!(budget, vec![MIN_DAEMON_LIMIT, MIN_DAEMON_LIMIT]);
+    }
+}
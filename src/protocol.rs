@@ -0,0 +1,200 @@
+//! # 磁力链接协议处理程序
+//!
+//! 把宿主程序注册为系统的 `magnet:` 链接处理程序（Windows 与 Linux），
+//! 在浏览器中点击磁力链接时系统会以 `<程序> <参数>... <链接>` 启动宿主程序，
+//! 宿主程序再通过 [`forward_link`] 把链接转交给正在运行的守护进程。
+//!
+//! 命令行工具可以直接作为桥接程序：`register_magnet_handler(&exe, &["add"])`。
+//! 浏览器启动宿主程序时通常没有设置密钥环境变量，密钥用 [`save_handler_secret`] 保存到只有当前用户可读的文件中。
+
+use std::path::{Path, PathBuf};
+#[cfg(any(windows, all(unix, not(target_os = "macos"))))]
+use std::process::Command;
+
+use crate::{get_burncloud_state_dir, Aria2Config, Aria2Error, Aria2Result, Aria2RpcClient};
+
+/// 把链接转交给按 `config` 的端口和密钥运行的守护进程，返回任务 GID
+pub async fn forward_link(config: &Aria2Config, link: &str) -> Aria2Result<String> {
+    if !link.starts_with("magnet:") {
+        return Err(Aria2Error::ConfigError(format!("不是磁力链接: {}", link)));
+    }
+    let client = Aria2RpcClient::new(config.port, config.secret.clone());
+    client.add_uri(vec![link.to_string()], None).await
+}
+
+/// 保存处理程序使用的 RPC 密钥，返回密钥文件路径；`secret` 为 None 时删除文件并返回 None
+///
+/// Unix 上文件权限为 0600，Windows 上位于当前用户的应用数据目录中。
+pub fn save_handler_secret(secret: Option<&str>) -> Aria2Result<Option<PathBuf>> {
+    use std::io::Write;
+
+    let path = get_burncloud_state_dir().join("magnet-handler.secret");
+    let Some(secret) = secret else {
+        match std::fs::remove_file(&path) {
+            Ok(()) => return Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Aria2Error::FileError(format!("删除 {} 失败: {}", path.display(), e))),
+        }
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Aria2Error::FileError(format!("创建 {} 失败: {}", dir.display(), e)))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // 已存在的文件不会按 mode 修改权限
+        if path.exists() {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| Aria2Error::FileError(format!("设置 {} 权限失败: {}", path.display(), e)))?;
+        }
+    }
+    options.open(&path)
+        .and_then(|mut file| file.write_all(secret.as_bytes()))
+        .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", path.display(), e)))?;
+    Ok(Some(path))
+}
+
+#[cfg(any(windows, all(unix, not(target_os = "macos"))))]
+fn run(command: &mut Command) -> Aria2Result<()> {
+    let output = command.output()
+        .map_err(|e| Aria2Error::ProcessError(format!("执行 {:?} 失败: {}", command.get_program(), e)))?;
+    if !output.status.success() {
+        return Err(Aria2Error::ProcessError(format!(
+            "{:?} 执行失败: {}",
+            command.get_program(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        )));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Windows：HKEY_CURRENT_USER\Software\Classes\magnet
+// ============================================================================
+
+#[cfg(windows)]
+const MAGNET_KEY: &str = r"HKCU\Software\Classes\magnet";
+
+/// 把 `exe` 注册为当前用户的磁力链接处理程序，链接作为最后一个参数传入
+#[cfg(windows)]
+pub fn register_magnet_handler(exe: &Path, args: &[&str]) -> Aria2Result<()> {
+    let mut command_line = format!("\"{}\"", exe.display());
+    for arg in args {
+        command_line.push_str(&format!(" \"{}\"", arg));
+    }
+    command_line.push_str(" \"%1\"");
+
+    run(Command::new("reg").args(["add", MAGNET_KEY, "/ve", "/d", "URL:Magnet Protocol", "/f"]))?;
+    run(Command::new("reg").args(["add", MAGNET_KEY, "/v", "URL Protocol", "/d", "", "/f"]))?;
+    let command_key = format!(r"{}\shell\open\command", MAGNET_KEY);
+    run(Command::new("reg").args(["add", &command_key, "/ve", "/d", &command_line, "/f"]))?;
+    tracing::info!(exe = %exe.display(), "已注册磁力链接处理程序");
+    Ok(())
+}
+
+/// 取消注册，当前处理程序不是 `exe` 时保留其他程序的注册
+#[cfg(windows)]
+pub fn unregister_magnet_handler(exe: &Path) -> Aria2Result<()> {
+    let command_key = format!(r"{}\shell\open\command", MAGNET_KEY);
+    let output = Command::new("reg").args(["query", &command_key, "/ve"]).output()
+        .map_err(|e| Aria2Error::ProcessError(format!("执行 reg 失败: {}", e)))?;
+    if !String::from_utf8_lossy(&output.stdout).contains(&exe.display().to_string()) {
+        return Ok(());
+    }
+    run(Command::new("reg").args(["delete", MAGNET_KEY, "/f"]))?;
+    tracing::info!(exe = %exe.display(), "已取消注册磁力链接处理程序");
+    Ok(())
+}
+
+// ============================================================================
+// Linux：~/.local/share/applications 中的 .desktop 文件 + xdg-mime
+// ============================================================================
+
+#[cfg(all(unix, not(target_os = "macos")))]
+const DESKTOP_FILE: &str = "burncloud-magnet.desktop";
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn desktop_file() -> Aria2Result<std::path::PathBuf> {
+    crate::xdg_dir("XDG_DATA_HOME", ".local/share")
+        .map(|dir| dir.join("applications").join(DESKTOP_FILE))
+        .ok_or_else(|| Aria2Error::ConfigError("无法确定应用程序目录（未设置 HOME）".to_string()))
+}
+
+/// 把 `exe` 注册为当前用户的磁力链接处理程序，链接作为最后一个参数传入
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn register_magnet_handler(exe: &Path, args: &[&str]) -> Aria2Result<()> {
+    // Exec 中的参数用双引号包裹，引号内的 " ` $ \ 需要用反斜杠转义；
+    // 键值本身还要按字符串规则转义一次，所以反斜杠要写成两个，% 写成 %% 以免被当作字段代码
+    let quote = |s: &str| {
+        let mut quoted = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' | '`' | '$' => {
+                    quoted.push_str("\\\\");
+                    quoted.push(c);
+                }
+                '\\' => quoted.push_str("\\\\\\\\"),
+                '%' => quoted.push_str("%%"),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    };
+    let mut exec = quote(&exe.display().to_string());
+    for arg in args {
+        exec.push(' ');
+        exec.push_str(&quote(arg));
+    }
+
+    let path = desktop_file()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Aria2Error::FileError(format!("创建 {} 失败: {}", dir.display(), e)))?;
+    }
+    let entry = format!(
+        "[Desktop Entry]\nType=Application\nName=BurnCloud Download\nExec={} %u\nNoDisplay=true\nMimeType=x-scheme-handler/magnet;\n",
+        exec,
+    );
+    std::fs::write(&path, entry)
+        .map_err(|e| Aria2Error::FileError(format!("写入 {} 失败: {}", path.display(), e)))?;
+
+    run(Command::new("xdg-mime").args(["default", DESKTOP_FILE, "x-scheme-handler/magnet"]))?;
+    tracing::info!(exe = %exe.display(), "已注册磁力链接处理程序");
+    Ok(())
+}
+
+/// 取消注册，删除 .desktop 文件后系统会回退到其他处理程序
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn unregister_magnet_handler(exe: &Path) -> Aria2Result<()> {
+    let path = desktop_file()?;
+    match std::fs::remove_file(&path) {
+        Ok(()) => {
+            tracing::info!(exe = %exe.display(), "已取消注册磁力链接处理程序");
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Aria2Error::FileError(format!("删除 {} 失败: {}", path.display(), e))),
+    }
+}
+
+// ============================================================================
+// 其他平台
+// ============================================================================
+
+/// 当前平台不支持注册协议处理程序
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+pub fn register_magnet_handler(_exe: &Path, _args: &[&str]) -> Aria2Result<()> {
+    Err(Aria2Error::ConfigError("当前平台不支持注册磁力链接处理程序".to_string()))
+}
+
+/// 当前平台不支持注册协议处理程序
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+pub fn unregister_magnet_handler(_exe: &Path) -> Aria2Result<()> {
+    Err(Aria2Error::ConfigError("当前平台不支持注册磁力链接处理程序".to_string()))
+}
@@ -0,0 +1,331 @@
+//! # 测试辅助
+//!
+//! 启动一个隔离的临时 aria2 守护进程：随机的空闲端口、临时目录和随机密钥，
+//! 不会终止本机上的其他 aria2 进程，可以在并行运行的测试中使用。
+//!
+//! aria2 可执行文件按以下顺序查找：环境变量 `ARIA2C_PATH`、
+//! BurnCloud 目录中已下载的 aria2、`PATH` 中的 `aria2c`。
+//!
+//! 没有安装 aria2 时可以使用 [`FakeManager`]，它在内存中模拟下载进度，
+//! 与 [`Aria2Manager`] 实现同一个 [`DownloadManager`] 接口。
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+use crate::{
+    task_gid, Aria2Config, Aria2Daemon, Aria2Error, Aria2Manager, Aria2Result, Aria2RpcClient, Aria2TaskError,
+    DownloadEvent, DownloadManager, DownloadOptions, DownloadStatus,
+};
+
+// 模拟下载推进进度的周期
+const FAKE_TICK: Duration = Duration::from_millis(200);
+
+/// 启动临时守护进程，返回相互关联的守护进程、RPC 客户端和管理器
+///
+/// 守护进程归管理器所有，测试结束时调用 `manager.shutdown()` 关闭；
+/// 临时目录为 `manager.config().download_dir` 的上一级，需要时由调用方删除。
+pub async fn spawn_test_daemon() -> Aria2Result<(Aria2Daemon, Aria2RpcClient, Aria2Manager)> {
+    let secret = uuid::Uuid::new_v4().simple().to_string();
+    let root = std::env::temp_dir().join(format!("burncloud-aria2-test-{}", &secret[..12]));
+    let download_dir = root.join("downloads");
+    std::fs::create_dir_all(&download_dir)
+        .map_err(|e| Aria2Error::FileError(format!("创建临时目录失败: {}", e)))?;
+
+    let config = Aria2Config {
+        port: free_port()?,
+        secret: Some(secret),
+        download_dir,
+        aria2_path: aria2_binary(),
+        kill_existing: false,
+        retry_on_reconnect: false,
+        bt_tracker_list_url: None,
+        // 所有状态文件都放在临时目录中，不读写本机 BurnCloud 目录和 aria2 的 DHT 缓存
+        history_file: Some(root.join("history.jsonl")),
+        stats_file: Some(root.join("stats.json")),
+        event_log: Some(root.join("events.jsonl")),
+        extra_args: vec![
+            format!("--dht-file-path={}", root.join("dht.dat").display()),
+            format!("--dht-file-path6={}", root.join("dht6.dat").display()),
+        ],
+        ..Default::default()
+    };
+
+    let manager = Aria2Manager::with_config(config);
+    manager.start_daemon().await?;
+
+    let daemon = manager.inner.daemon.lock().unwrap().clone()
+        .ok_or_else(|| Aria2Error::DaemonError("守护进程未运行".to_string()))?;
+    let client = manager.create_rpc_client()
+        .ok_or_else(|| Aria2Error::DaemonError("守护进程未运行".to_string()))?;
+    Ok((daemon, client, manager))
+}
+
+/// 由系统分配一个当前空闲的端口
+fn free_port() -> Aria2Result<u16> {
+    std::net::TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| Aria2Error::PortError(format!("分配端口失败: {}", e)))
+}
+
+fn aria2_binary() -> PathBuf {
+    if let Some(path) = std::env::var_os("ARIA2C_PATH") {
+        return PathBuf::from(path);
+    }
+    let downloaded = Aria2Config::default().aria2_path;
+    if downloaded.exists() {
+        return downloaded;
+    }
+    PathBuf::from("aria2c")
+}
+
+// ============================================================================
+// 内存中的模拟管理器
+// ============================================================================
+
+/// 不依赖 aria2 的下载管理器，在内存中按固定速度模拟下载进度
+///
+/// 新任务立即开始下载，每个周期按 `speed` 增加已下载字节数，达到 `file_size` 后完成，
+/// 与 [`Aria2Manager`] 一样通过事件通知状态变化，去重规则也相同。
+/// 进度由后台任务推进，需要在 tokio 运行时中创建；最后一个克隆被丢弃后后台任务自动退出。
+#[derive(Clone)]
+pub struct FakeManager {
+    inner: Arc<FakeInner>,
+}
+
+struct FakeInner {
+    tasks: Mutex<Vec<FakeTask>>,
+    events: broadcast::Sender<DownloadEvent>,
+    file_size: u64,
+    speed: u64,
+}
+
+struct FakeTask {
+    status: DownloadStatus,
+    path: String,
+    started: bool,
+}
+
+impl FakeManager {
+    /// 每个任务 10 MiB，下载速度 1 MiB/s
+    pub fn new() -> Self {
+        Self::with_speed(10 * 1024 * 1024, 1024 * 1024)
+    }
+
+    /// 指定每个任务的文件大小和下载速度（字节/秒）
+    pub fn with_speed(file_size: u64, speed: u64) -> Self {
+        let (events, _) = broadcast::channel(256);
+        let inner = Arc::new(FakeInner {
+            tasks: Mutex::new(Vec::new()),
+            events,
+            file_size,
+            speed,
+        });
+        tokio::spawn(run_fake(Arc::downgrade(&inner)));
+        Self { inner }
+    }
+
+    /// 让任务以 aria2 错误码 `code` 失败，用于测试失败时的界面流程
+    pub fn fail(&self, gid: &str, code: u32) -> Aria2Result<()> {
+        let path = {
+            let mut tasks = self.inner.tasks.lock().unwrap();
+            let task = find(&mut tasks, gid)?;
+            task.status.status = "error".to_string();
+            task.status.download_speed = "0".to_string();
+            task.status.connections = "0".to_string();
+            task.status.error_code = Some(code.to_string());
+            task.status.error_message = Some("模拟的下载失败".to_string());
+            task.path.clone()
+        };
+        let error = Aria2TaskError::from_code(&code.to_string());
+        let _ = self.inner.events.send(DownloadEvent::Failed { gid: gid.to_string(), path, error });
+        Ok(())
+    }
+}
+
+impl Default for FakeManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn find<'a>(tasks: &'a mut [FakeTask], gid: &str) -> Aria2Result<&'a mut FakeTask> {
+    tasks.iter_mut()
+        .find(|t| t.status.gid == gid)
+        .ok_or_else(|| Aria2Error::RpcError(format!("任务不存在: {}", gid)))
+}
+
+/// 按周期推进所有下载中的任务
+async fn run_fake(inner: Weak<FakeInner>) {
+    let mut ticker = tokio::time::interval(FAKE_TICK);
+    loop {
+        ticker.tick().await;
+        let Some(inner) = inner.upgrade() else { break };
+
+        let step = (inner.speed as f64 * FAKE_TICK.as_secs_f64()) as u64;
+        let mut events = Vec::new();
+        for task in inner.tasks.lock().unwrap().iter_mut().filter(|t| t.status.status == "active") {
+            let status = &mut task.status;
+            if !task.started {
+                task.started = true;
+                events.push(DownloadEvent::Started { gid: status.gid.clone() });
+            }
+            let completed = status.completed_length.parse::<u64>().unwrap_or(0);
+            let completed = (completed + step.max(1)).min(inner.file_size);
+            status.completed_length = completed.to_string();
+            if completed >= inner.file_size {
+                status.status = "complete".to_string();
+                status.download_speed = "0".to_string();
+                status.connections = "0".to_string();
+                events.push(DownloadEvent::Completed { gid: status.gid.clone(), path: task.path.clone() });
+            } else {
+                status.download_speed = inner.speed.to_string();
+                status.connections = "1".to_string();
+            }
+        }
+        for event in events {
+            let _ = inner.events.send(event);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DownloadManager for FakeManager {
+    async fn add_download(&self, uris: Vec<String>, options: Option<DownloadOptions>) -> Aria2Result<String> {
+        let first_uri = uris.first()
+            .ok_or_else(|| Aria2Error::ConfigError("URI 列表为空".to_string()))?;
+        let options = options.unwrap_or_default();
+        options.validate()?;
+        let gid = options.gid.clone().unwrap_or_else(|| task_gid(first_uri, Some(&options)));
+
+        {
+            let mut tasks = self.inner.tasks.lock().unwrap();
+            match tasks.iter().position(|t| t.status.gid == gid) {
+                // 与 aria2 相同：失败或被移除的任务重新下载，其他状态直接返回原任务
+                Some(index) if matches!(tasks[index].status.status.as_str(), "error" | "removed") => {
+                    tasks.remove(index);
+                }
+                Some(_) => return Ok(gid),
+                None => {}
+            }
+
+            let name = options.out.clone().unwrap_or_else(|| {
+                let path = first_uri.split(['?', '#']).next().unwrap_or_default();
+                path.rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or(&gid).to_string()
+            });
+            let dir = options.dir.as_deref().unwrap_or("downloads");
+            tasks.push(FakeTask {
+                status: DownloadStatus {
+                    gid: gid.clone(),
+                    status: "active".to_string(),
+                    total_length: self.inner.file_size.to_string(),
+                    completed_length: "0".to_string(),
+                    download_speed: "0".to_string(),
+                    upload_length: "0".to_string(),
+                    upload_speed: "0".to_string(),
+                    connections: "0".to_string(),
+                    num_seeders: None,
+                    error_code: None,
+                    error_message: None,
+                    verified_length: None,
+                },
+                path: Path::new(dir).join(name).to_string_lossy().into_owned(),
+                started: false,
+            });
+        }
+        let _ = self.inner.events.send(DownloadEvent::Added { gid: gid.clone() });
+        Ok(gid)
+    }
+
+    async fn remove_download(&self, gid: &str) -> Aria2Result<()> {
+        {
+            let mut tasks = self.inner.tasks.lock().unwrap();
+            let task = find(&mut tasks, gid)?;
+            if task.status.status != "active" {
+                return Ok(());
+            }
+            task.status.status = "removed".to_string();
+            task.status.download_speed = "0".to_string();
+            task.status.connections = "0".to_string();
+        }
+        let _ = self.inner.events.send(DownloadEvent::Removed { gid: gid.to_string() });
+        Ok(())
+    }
+
+    async fn download_now(&self, gid: &str) -> Aria2Result<()> {
+        let mut tasks = self.inner.tasks.lock().unwrap();
+        let task = find(&mut tasks, gid)?;
+        match task.status.status.as_str() {
+            "active" => Ok(()),
+            other => Err(Aria2Error::ConfigError(format!("任务已结束（{}）: {}", other, gid))),
+        }
+    }
+
+    fn get_progress(&self, gid: &str) -> Option<DownloadStatus> {
+        self.inner.tasks.lock().unwrap().iter()
+            .find(|t| t.status.gid == gid)
+            .map(|t| t.status.clone())
+    }
+
+    fn list_tasks(&self) -> Vec<DownloadStatus> {
+        self.inner.tasks.lock().unwrap().iter().map(|t| t.status.clone()).collect()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<DownloadEvent> {
+        self.inner.events.subscribe()
+    }
+
+    fn is_running(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fake_manager_completes_downloads() {
+        let manager = FakeManager::with_speed(1000, 10_000);
+        let mut events = manager.subscribe();
+        let gid = manager.add_download(vec!["https://example.com/a.bin".to_string()], None).await.unwrap();
+
+        // 相同的任务不会重复添加
+        let again = manager.add_download(vec!["https://example.com/a.bin".to_string()], None).await.unwrap();
+        assert_eq!(gid, again);
+        assert_eq!(manager.list_tasks().len(), 1);
+
+        let completed = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let DownloadEvent::Completed { gid: done, path } = events.recv().await.unwrap() {
+                    break (done, path);
+                }
+            }
+        }).await.unwrap();
+        assert_eq!(completed.0, gid);
+        assert!(completed.1.ends_with("a.bin"));
+
+        let status = manager.get_progress(&gid).unwrap();
+        assert_eq!(status.status, "complete");
+        assert_eq!(status.percent(), Some(100.0));
+    }
+
+    #[tokio::test]
+    async fn failed_fake_task_can_be_added_again() {
+        let manager = FakeManager::with_speed(1000, 1);
+        let uris = vec!["https://example.com/b.bin".to_string()];
+        let gid = manager.add_download(uris.clone(), None).await.unwrap();
+
+        manager.fail(&gid, 6).unwrap();
+        let status = manager.get_progress(&gid).unwrap();
+        assert_eq!(status.status, "error");
+        assert_eq!(status.task_error(), Some(Aria2TaskError::NetworkError));
+
+        assert_eq!(manager.add_download(uris, None).await.unwrap(), gid);
+        assert_eq!(manager.get_progress(&gid).unwrap().status, "active");
+        assert!(manager.fail("0000000000000000", 6).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! # 终端监视界面
+//!
+//! 在终端中实时显示下载队列、速度和守护进程状态，适合在没有图形界面的服务器上查看下载情况。
+//! 所有数据来自 [`Aria2Manager`] 的快照、统计和事件，不单独发起 RPC。
+
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+
+use crate::{format, Aria2Error, Aria2Manager, Aria2Result, DownloadEvent, DownloadStatus};
+
+// 界面刷新间隔，也是按键响应的最长延迟
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+// 守护进程没有 RPC 端口持续该时长后（重启次数用尽且没有备用进程）退出界面并报错
+const DAEMON_LOST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 运行监视界面，直到按下 q 或 Esc
+///
+/// 只负责显示，退出时不会关闭守护进程；守护进程停止运行时返回错误。
+pub async fn run(manager: &Aria2Manager) -> Aria2Result<()> {
+    let mut terminal = ratatui::try_init()
+        .map_err(|e| Aria2Error::DaemonError(format!("初始化终端失败: {}", e)))?;
+    let result = event_loop(&mut terminal, manager).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(terminal: &mut ratatui::DefaultTerminal, manager: &Aria2Manager) -> Aria2Result<()> {
+    let mut events = manager.subscribe();
+    let mut restarts = 0;
+    let mut lost_since: Option<std::time::Instant> = None;
+    let mut ticker = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        ticker.tick().await;
+        // 切换到备用守护进程时端口会短暂消失，持续一段时间才认为守护进程已停止
+        if manager.rpc_port().is_some() {
+            lost_since = None;
+        } else if lost_since.get_or_insert_with(std::time::Instant::now).elapsed() >= DAEMON_LOST_TIMEOUT {
+            return Err(Aria2Error::DaemonError("aria2 守护进程已停止运行".to_string()));
+        }
+        while let Ok(event) = events.try_recv() {
+            if let DownloadEvent::DaemonRestarted { .. } = event {
+                restarts += 1;
+            }
+        }
+
+        let tasks = manager.list_tasks();
+        terminal.draw(|frame| draw(frame, manager, &tasks, restarts))
+            .map_err(|e| Aria2Error::DaemonError(format!("绘制界面失败: {}", e)))?;
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            if let Ok(Event::Key(key)) = event::read() {
+                if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, manager: &Aria2Manager, tasks: &[DownloadStatus], restarts: u32) {
+    let [header, queue, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let number = |value: &str| value.parse::<u64>().unwrap_or(0);
+    let download_speed: u64 = tasks.iter().map(|t| number(&t.download_speed)).sum();
+    let upload_speed: u64 = tasks.iter().map(|t| number(&t.upload_speed)).sum();
+    let health = match (manager.is_running(), manager.rpc_port()) {
+        (true, Some(port)) => format!("运行中（端口 {}）", port),
+        _ => "未运行".to_string(),
+    };
+    let session = manager.stats().session;
+    let summary = format!(
+        "守护进程: {}  重启 {} 次 | 下载 {}  上传 {} | 本次已下载 {}  已上传 {}",
+        health,
+        restarts,
+        format::speed(download_speed),
+        format::speed(upload_speed),
+        format::size(session.downloaded),
+        format::size(session.uploaded),
+    );
+    frame.render_widget(Paragraph::new(summary).block(Block::bordered().title("BurnCloud aria2")), header);
+
+    let rows = tasks.iter().map(|task| {
+        let total = number(&task.total_length);
+        Row::new(vec![
+            task.gid.clone(),
+            task.status.clone(),
+            task.percent().map_or_else(|| "--".to_string(), |p| format!("{:.1}%", p)),
+            format::size(total),
+            format::speed(number(&task.download_speed)),
+            task.eta().map_or_else(|| "--".to_string(), format::duration),
+            task.connections.clone(),
+        ])
+    });
+    let widths = [
+        Constraint::Length(16),
+        Constraint::Length(8),
+        Constraint::Length(7),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(8),
+        Constraint::Length(6),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(["GID", "状态", "进度", "大小", "速度", "剩余", "连接"])
+            .style(Style::new().add_modifier(Modifier::BOLD)))
+        .block(Block::bordered().title(format!("任务（{}）", tasks.len())));
+    frame.render_widget(table, queue);
+
+    frame.render_widget(Paragraph::new("q/Esc 退出"), footer);
+}
@@ -0,0 +1,174 @@
+//! # AriaNg 网页界面
+//!
+//! 下载 AriaNg（AllInOne 版本）并在本地端口上提供服务，打开即连接到托管的 aria2。
+//! 浏览器的 RPC 请求经由同源的 `/jsonrpc` 转发给 aria2，
+//! 因此不需要开启 aria2 的 `--rpc-allow-origin-all`，守护进程换端口后也无需重新配置。
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use base64::Engine;
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::{download_file, get_burncloud_dir, http_client, try_http_client, Aria2Error, Aria2Manager, Aria2Result};
+
+const ARIANG_URL: &str = "https://github.com/mayswind/AriaNg/releases/download/1.3.7/AriaNg-1.3.7-AllInOne.zip";
+
+#[derive(Clone)]
+struct WebUiState {
+    manager: Aria2Manager,
+    client: Client,
+    index_html: Arc<String>,
+    addr: SocketAddr,
+}
+
+/// 下载 AriaNg 到 BurnCloud 目录，已存在时直接返回 index.html 的路径
+pub async fn download_ariang() -> Aria2Result<PathBuf> {
+    let target_dir = get_burncloud_dir().join("ariang");
+    let index_path = target_dir.join("index.html");
+    if index_path.exists() {
+        return Ok(index_path);
+    }
+
+    std::fs::create_dir_all(&target_dir)
+        .map_err(|e| Aria2Error::DownloadError(format!("创建目录失败: {}", e)))?;
+
+    let client = try_http_client()?;
+    let zip_path = target_dir.join("ariang.zip");
+    download_file(&client, ARIANG_URL, &zip_path, &mut |_, _| {}).await?;
+
+    let file = std::fs::File::open(&zip_path)
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+    let mut entry = archive.by_name("index.html")
+        .map_err(|_| Aria2Error::DownloadError("ZIP文件中未找到 index.html".to_string()))?;
+    let mut out_file = std::fs::File::create(&index_path)
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+    std::io::copy(&mut entry, &mut out_file)
+        .map_err(|e| Aria2Error::DownloadError(e.to_string()))?;
+
+    let _ = std::fs::remove_file(&zip_path);
+    Ok(index_path)
+}
+
+/// 在指定地址上提供 AriaNg，直到出错才返回
+///
+/// 访问 `http://<addr>/` 会跳转到已填好 RPC 地址和密钥的 AriaNg 页面。
+/// 该页面可以完全控制 aria2，应只绑定在 127.0.0.1 上。
+pub async fn serve(manager: Aria2Manager, addr: SocketAddr) -> Aria2Result<()> {
+    let index_path = download_ariang().await?;
+    let index_html = std::fs::read_to_string(&index_path)
+        .map_err(|e| Aria2Error::DownloadError(format!("读取 AriaNg 失败: {}", e)))?;
+
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .map_err(|e| Aria2Error::PortError(format!("绑定 {} 失败: {}", addr, e)))?;
+    let addr = listener.local_addr()
+        .map_err(|e| Aria2Error::PortError(e.to_string()))?;
+
+    let state = WebUiState {
+        manager,
+        client: http_client(),
+        index_html: Arc::new(index_html),
+        addr,
+    };
+    let app = Router::new()
+        .route("/", get(redirect_to_ui))
+        .route("/index.html", get(index))
+        .route("/jsonrpc", post(proxy_rpc))
+        .with_state(state);
+
+    axum::serve(listener, app).await
+        .map_err(|e| Aria2Error::DaemonError(format!("网页界面服务异常退出: {}", e)))
+}
+
+async fn redirect_to_ui(State(state): State<WebUiState>) -> Redirect {
+    // AriaNg 约定的预配置链接，密钥需要 base64 编码
+    let secret = state.manager.config().secret.unwrap_or_default();
+    let secret = base64::engine::general_purpose::URL_SAFE.encode(secret);
+    Redirect::temporary(&format!(
+        "/index.html#!/settings/rpc/set/http/{}/{}/jsonrpc/{}",
+        state.addr.ip(),
+        state.addr.port(),
+        secret
+    ))
+}
+
+async fn index(State(state): State<WebUiState>) -> Html<String> {
+    Html(state.index_html.as_ref().clone())
+}
+
+async fn proxy_rpc(State(state): State<WebUiState>, body: Bytes) -> Response {
+    // 只有添加任务才重新启动空闲时关闭的 aria2，AriaNg 的定时刷新不会让它一直保持运行
+    if adds_task(&body) {
+        if let Err(e) = state.manager.restart_if_idle().await {
+            return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response();
+        }
+    }
+    let Some(port) = state.manager.rpc_port() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "aria2 守护进程未运行").into_response();
+    };
+
+    let result = state.client
+        .post(format!("http://localhost:{}/jsonrpc", port))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await;
+
+    match result {
+        Ok(response) => {
+            let status = StatusCode::from_u16(response.status().as_u16())
+                .unwrap_or(StatusCode::BAD_GATEWAY);
+            match response.bytes().await {
+                Ok(bytes) => (status, [(header::CONTENT_TYPE, "application/json")], bytes).into_response(),
+                Err(e) => (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+            }
+        }
+        Err(e) => (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    }
+}
+
+/// 请求（包括批量请求和 system.multicall）中是否有添加任务的调用
+fn adds_task(body: &[u8]) -> bool {
+    fn is_add(method: Option<&str>) -> bool {
+        method.is_some_and(|m| m.starts_with("aria2.add"))
+    }
+    fn call_adds_task(call: &Value) -> bool {
+        match call.get("method").and_then(Value::as_str) {
+            Some("system.multicall") => call.pointer("/params/0")
+                .and_then(Value::as_array)
+                .is_some_and(|calls| calls.iter().any(|c| is_add(c.get("methodName").and_then(Value::as_str)))),
+            method => is_add(method),
+        }
+    }
+
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(calls)) => calls.iter().any(call_adds_task),
+        Ok(call) => call_adds_task(&call),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_add_calls() {
+        assert!(adds_task(br#"{"jsonrpc":"2.0","id":1,"method":"aria2.addUri","params":[["https://example.com/a"]]}"#));
+        assert!(adds_task(br#"[{"method":"aria2.tellActive"},{"method":"aria2.addTorrent","params":["AAAA"]}]"#));
+        assert!(adds_task(br#"{"method":"system.multicall","params":[[{"methodName":"aria2.addMetalink","params":[]}]]}"#));
+        assert!(!adds_task(br#"{"method":"system.multicall","params":[[{"methodName":"aria2.tellActive"}]]}"#));
+        assert!(!adds_task(br#"{"method":"aria2.getGlobalStat"}"#));
+        assert!(!adds_task(b"not json"));
+    }
+}